@@ -1,4 +1,3 @@
-use std::fs;
 use std::env;
 use std::collections::HashMap;
 
@@ -61,17 +60,17 @@ fn rotate_robot(current_orientation: &mut Orientation, position: (i32, i32), dir
 fn paint(computer: &mut Computer, hull: &mut HashMap<(i32, i32), i32>, start: (i32, i32)) -> usize {
     let mut pos = start;
     let mut robot_orientation = Orientation::UP;
+    let options = RunOptions::new().outputs(2);
     loop {
         let panel_color = hull.get(&pos).unwrap_or(&1);
         computer.add_input(*panel_color);
-        let state = computer.run();
-        let mut new_color = computer.get_output().unwrap();
-        let mut rotation = computer.get_output().unwrap();
-        hull.insert(pos, new_color as i32);
-        pos = rotate_robot(&mut robot_orientation, pos, rotation as i32);
-        if state == State::Done {
+        if computer.run_with(&options).unwrap() != State::Output {
             break;
         }
+        let new_color = computer.get_output().unwrap();
+        let rotation = computer.get_output().unwrap();
+        hull.insert(pos, new_color as i32);
+        pos = rotate_robot(&mut robot_orientation, pos, rotation as i32);
     }
 
     hull.keys().len()
@@ -82,13 +81,7 @@ fn main() {
     let args: Vec<String> = env::args().collect();
     let input_filename = args[1].clone();
 
-    let file_contents = fs::read_to_string(&input_filename).unwrap_or_else(|err| {
-        eprintln!("Error : {}", err);
-        eprintln!("Cannot read from file {}", input_filename);
-        std::process::exit(1);
-    });
-
-    let instructions = int_computer::computer::read_instructions(&file_contents);
+    let instructions = load_program_or_exit(&input_filename);
     let mut computer = Computer::new(&instructions);
 
     let mut hull:HashMap<(i32, i32), i32> = HashMap::new();
@@ -1,11 +1,10 @@
 use std::env;
-use std::fs;
 
 extern crate int_computer;
 use int_computer::computer::*;
 
 fn part1(computer: &mut Computer) -> usize {
-    computer.run();
+    computer.run().unwrap();
     let output = computer.get_all_output();
     let block_tiles = output.chunks(3).into_iter().filter(|c| c[2] == 2).count();
 
@@ -15,6 +14,7 @@ fn part1(computer: &mut Computer) -> usize {
 
 fn part2(instructions: &Vec<i128>) {
     let mut computer = Computer::new(&instructions);
+    computer.set_instruction_cache(true);
     let mut pad_x = 0;
     let mut ball_x = 0;
     let mut total_score = 0;
@@ -22,7 +22,7 @@ fn part2(instructions: &Vec<i128>) {
     computer.memwrite(0, 2);
 
     loop {
-        let state = computer.run();
+        let state = computer.run().unwrap();
         let output = computer.get_all_output();
         output.chunks(3).into_iter().for_each(|c| {
             if c[0] == -1 {
@@ -46,13 +46,7 @@ fn main() {
     let args: Vec<String> = env::args().collect();
     let input_filename = args[1].clone();
 
-    let file_contents = fs::read_to_string(&input_filename).unwrap_or_else(|err| {
-        eprintln!("Error : {}", err);
-        eprintln!("Cannot read from file {}", input_filename);
-        std::process::exit(1);
-    });
-
-    let instructions = int_computer::computer::read_instructions(&file_contents);
+    let instructions = load_program_or_exit(&input_filename);
 
     part1(&mut Computer::new(&instructions.clone()));
     part2(&instructions);
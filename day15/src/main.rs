@@ -39,35 +39,19 @@ fn get_commands(current_position: &(i32, i32)) -> Vec<(Direction, (i32, i32))> {
     .collect()
 }
 
-fn discover(
-    computer: &mut Computer,
-    map: &mut HashMap<(i32, i32), i32>,
-    position: (i32, i32),
-    parent_direction: Option<Direction>,
-) {
+fn discover(computer: &Computer, map: &mut HashMap<(i32, i32), i32>, position: (i32, i32)) {
     for (direction, new_position) in get_commands(&position) {
         if !map.contains_key(&new_position) {
-            computer.add_input(direction as i32);
-            computer.run();
-            let out = computer.get_output().unwrap();
+            let mut droid = computer.clone();
+            droid.add_input(direction as i32);
+            droid.run_with(&RunOptions::new().outputs(1)).unwrap();
+            let out = droid.get_output().unwrap();
             map.insert(new_position, out as i32);
             if out != 0 {
-                discover(computer, map, new_position, Some(direction));
+                discover(&droid, map, new_position);
             }
         }
     }
-
-    if let Some(back_dir) = match parent_direction {
-        None => None,
-        Some(Direction::North) => Some(Direction::South),
-        Some(Direction::South) => Some(Direction::North),
-        Some(Direction::West) => Some(Direction::East),
-        Some(Direction::East) => Some(Direction::West),
-    } {
-        computer.add_input(back_dir as i32);
-        computer.run();
-        let _ = computer.get_output();
-    }
 }
 
 fn map_2_matrix(map: &HashMap<(i32, i32), i32>, start_position: &mut (i32, i32)) -> Vec<Vec<i32>> {
@@ -160,10 +144,11 @@ fn fill_map(map: &mut Vec<Vec<i32>>, start_position: (i32, i32)) {
 }
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let mut computer = Computer::new_from_file(&args[1]);
+    let mut computer = Computer::new(&load_program_or_exit(&args[1]));
+    computer.set_instruction_cache(true);
     let mut map: HashMap<(i32, i32), i32> = HashMap::new();
     let mut start_position = (25, 25);
-    discover(&mut computer, &mut map, start_position, None);
+    discover(&computer, &mut map, start_position);
 
     let mut mmap = map_2_matrix(&map, &mut start_position);
     let ox_position = search_oxygen(mmap.clone(), start_position);
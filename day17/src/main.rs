@@ -2,6 +2,7 @@ extern crate int_computer;
 extern crate regex;
 
 use std::env;
+use int_computer::ascii::AsciiComputer;
 use int_computer::computer::*;
 use itertools::Itertools;
 use num_derive::FromPrimitive;    
@@ -19,7 +20,7 @@ enum Orientation {
 
 fn get_view(computer: &mut Computer) -> Vec<Vec<u8>> {
     let mut view: Vec<Vec<u8>> = Vec::new();
-    computer.run();
+    computer.run().unwrap();
     let mut output: Vec<i128> = computer.get_all_output();
     let endline_pos = output.iter().find_position(|o| **o as i32 == 10);
     if let Some((line_length, _)) = endline_pos  {
@@ -37,15 +38,10 @@ fn get_view(computer: &mut Computer) -> Vec<Vec<u8>> {
     view
 }
 
-fn display(computer: &mut Computer) {
-    computer.run();
-    let last = computer.get_exit_value();
-    let output: Vec<i128> = computer.get_all_output();
-
-    for c in output {
-        print!("{}", (c as u8) as char);
-    }
-    println!("{:?}", last);
+fn display(computer: &mut AsciiComputer) {
+    let (output, _) = computer.run().unwrap();
+    print!("{}", output.text);
+    println!("{:?}", output.values.last());
 }
 
 fn get_neighbours(current_position: &(i32, i32)) -> Vec<(i32, i32)> {
@@ -257,16 +253,16 @@ fn split_routine(routine: String) -> String {
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let mut computer = Computer::new_from_file(&args[1]);
+    let program = load_program_or_exit(&args[1]);
+    let mut computer = Computer::new(&program);
     let view = get_view(&mut computer);
     println!("{:?}", get_intersections(&view));
 
     let routines= split_routine(get_move_routine(&view));
-    let mut part2computer = Computer::new_from_file(&args[1]);
+    let mut part2computer = Computer::new(&program);
     part2computer.memwrite(0, 2);
-    for c in routines.chars() {
-        part2computer.add_input(c as i32);
-    }
+    let mut part2computer = AsciiComputer::new(part2computer);
+    part2computer.send(&routines);
 
     display(&mut part2computer);
 }
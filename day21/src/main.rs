@@ -1,36 +1,23 @@
 extern crate int_computer;
 
 use std::env;
+use int_computer::ascii::AsciiComputer;
 use int_computer::computer::*;
 
-fn display(computer: &mut Computer) {
-    computer.run();
-    let last = computer.get_exit_value();
-    let output: Vec<i128> = computer.get_all_output();
-
-    for c in output {
-        print!("{}", (c as u8) as char);
+fn run(computer: &mut AsciiComputer, script: &Vec<&str>) {
+    for line in script {
+        computer.send_line(line);
     }
-    println!("{:?}", last);
-}
-
-fn run(computer: &mut Computer, script: &Vec<&str>) {
-    script
-        .iter()
-        .for_each(|s| {
-            for c in s.chars() {
-                computer.add_input(c as i32);
-            }
-            computer.add_input(10);
-        });
 
-    display(computer);
+    let (output, _) = computer.run().unwrap();
+    print!("{}", output.text);
+    println!("{:?}", output.values.last());
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let mut computer = Computer::new_from_file(&args[1]);
-    let mut computer2 = Computer::new_from_file(&args[1]);
+    let mut computer = AsciiComputer::new(Computer::new(&load_program_or_exit(&args[1])));
+    let mut computer2 = AsciiComputer::new(computer.computer().clone());
 
     //    J = !(A & B & C) & D
     run(&mut computer, &vec![
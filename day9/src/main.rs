@@ -1,5 +1,4 @@
 use std::env;
-use std::fs;
 
 extern crate int_computer;
 use int_computer::computer::*;
@@ -8,19 +7,14 @@ fn main() {
     let args: Vec<String> = env::args().collect();
     let input_filename = args[1].clone();
 
-    let file_contents = fs::read_to_string(&input_filename).unwrap_or_else(|err| {
-        eprintln!("Error : {}", err);
-        eprintln!("Cannot read from file {}", input_filename);
-        std::process::exit(1);
-    });
-    let instructions = int_computer::computer::read_instructions(&file_contents);
+    let instructions = load_program_or_exit(&input_filename);
     let mut computer = Computer::new(&instructions);
     computer.add_input(1);
-    computer.run();
+    computer.run().unwrap();
 
     let mut computer2 = Computer::new(&instructions);
     computer2.add_input(2);
-    computer2.run();
+    computer2.run().unwrap();
 
     println!("BOOST keycode = {}", computer.get_output().unwrap());
     println!("Distress coordinates = {}", computer2.get_output().unwrap());
@@ -0,0 +1,54 @@
+//! Runs a program on every memory backend, with and without the instruction cache,
+//! and reports how long each took.
+//!
+//! cargo run --release -p int_computer --example bench -- day9/input 2
+use std::env;
+use std::time::Instant;
+
+extern crate int_computer;
+use int_computer::computer::*;
+use int_computer::memory::{FlatMemory, Memory, SparseMemory};
+
+fn bench<M: Memory<Word = i128>>(name: &str, program: &[i128], inputs: &[i128], cached: bool, rounds: u32) {
+    let start = Instant::now();
+    let mut output = None;
+    for _ in 0..rounds {
+        let mut computer = Computer::with_memory(M::from_program(program));
+        computer.set_instruction_cache(cached);
+        for i in inputs {
+            computer.add_input_128(*i);
+        }
+        computer.run().unwrap();
+        output = computer.get_exit_value();
+    }
+    println!(
+        "{:<8} {:<8} {:>10.3} ms/run  (last output {:?})",
+        name,
+        if cached { "cached" } else { "" },
+        start.elapsed().as_secs_f64() * 1000.0 / rounds as f64,
+        output
+    );
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} <program file> [input...]", args[0]);
+        std::process::exit(1);
+    }
+    let file_contents = std::fs::read_to_string(&args[1]).unwrap_or_else(|err| {
+        eprintln!("Error : {}", err);
+        eprintln!("Cannot read from file {}", args[1]);
+        std::process::exit(1);
+    });
+    let program = parse_program(&file_contents).unwrap_or_else(|err| {
+        eprintln!("Error : {}", err);
+        std::process::exit(1);
+    });
+    let inputs: Vec<i128> = args[2..].iter().map(|a| a.parse().unwrap()).collect();
+
+    for &cached in &[false, true] {
+        bench::<SparseMemory>("sparse", &program, &inputs, cached, 5);
+        bench::<FlatMemory>("flat", &program, &inputs, cached, 5);
+    }
+}
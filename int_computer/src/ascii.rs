@@ -0,0 +1,141 @@
+use crate::computer::{Computer, RunOptions, State, VmError};
+use crate::memory::{FlatMemory, Memory};
+use crate::word::Word;
+
+/// Output of an ASCII program, split into characters and the values outside the ASCII range,
+/// which such programs use for numeric answers.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct AsciiText<W = i128> {
+    pub text: String,
+    pub values: Vec<W>,
+}
+
+impl<W: Word> AsciiText<W> {
+    fn push(&mut self, value: W) {
+        match value.to_i128().filter(|v| (0..128).contains(v)) {
+            Some(code) => self.text.push(code as u8 as char),
+            None => self.values.push(value),
+        }
+    }
+}
+
+pub fn decode_output<W: Word>(output: &[W]) -> AsciiText<W> {
+    let mut decoded = AsciiText::default();
+    for value in output {
+        decoded.push(value.clone());
+    }
+    decoded
+}
+
+/// Drives a program that talks in lines of text, like day 17's vacuum robot or day 21's
+/// springdroid.
+pub struct AsciiComputer<M: Memory = FlatMemory> {
+    computer: Computer<M>,
+}
+
+impl<M: Memory> AsciiComputer<M> {
+    pub fn new(computer: Computer<M>) -> AsciiComputer<M> {
+        AsciiComputer { computer }
+    }
+
+    pub fn computer(&self) -> &Computer<M> {
+        &self.computer
+    }
+
+    pub fn computer_mut(&mut self) -> &mut Computer<M> {
+        &mut self.computer
+    }
+
+    pub fn into_computer(self) -> Computer<M> {
+        self.computer
+    }
+
+    /// Queues the character codes of `text`.
+    pub fn send(&mut self, text: &str) {
+        for b in text.bytes() {
+            self.computer.add_input(b as i32);
+        }
+    }
+
+    /// Queues `line` followed by a newline.
+    pub fn send_line(&mut self, line: &str) {
+        self.send(line);
+        self.computer.add_input(10);
+    }
+
+    /// Runs until the program needs input or halts and returns what it printed.
+    pub fn run(&mut self) -> Result<(AsciiText<M::Word>, State), VmError> {
+        let state = self.computer.run()?;
+        Ok((decode_output(&self.computer.get_all_output()), state))
+    }
+
+    /// Runs until the printed text ends with `prompt`, returning `State::Output`, or until
+    /// the program needs input or halts.
+    pub fn read_until_prompt(
+        &mut self,
+        prompt: &str,
+    ) -> Result<(AsciiText<M::Word>, State), VmError> {
+        let options = RunOptions::new().outputs(1);
+        let mut decoded = AsciiText::default();
+        loop {
+            let state = self.computer.run_with(&options)?;
+            for value in self.computer.get_all_output() {
+                decoded.push(value);
+            }
+            if state != State::Output || decoded.text.ends_with(prompt) {
+                return Ok((decoded, state));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn test_decode_output() {
+        let decoded = decode_output(&[72i128, 10, -5, 300, 105]);
+        assert_eq!(decoded.text, "H\ni");
+        assert_eq!(decoded.values, vec![-5, 300]);
+    }
+
+    #[test]
+    fn test_prompt() {
+        // greets, then prints 1000 + the length of the line it reads
+        let program = assemble(
+            "
+                    out #72
+                    out #105
+                    out #10
+                    out #62
+                    out #32
+            loop:   in [c]
+                    eq [c], #10, [flag]
+                    jt [flag], #done
+                    add [n], #1, [n]
+                    jt #1, #loop
+            done:   add [n], #1000, [n]
+                    out [n]
+                    out #10
+                    hlt
+            c:      .data 0
+            n:      .data 0
+            flag:   .data 0
+            ",
+        )
+        .unwrap();
+        let mut computer = AsciiComputer::new(Computer::new(&program));
+        let (greeting, state) = computer.read_until_prompt("\n").unwrap();
+        assert_eq!((greeting.text.as_str(), state), ("Hi\n", State::Output));
+        let (prompt, state) = computer.read_until_prompt("> ").unwrap();
+        assert_eq!((prompt.text.as_str(), state), ("> ", State::Output));
+
+        computer.send_line("abc");
+        let (reply, state) = computer.run().unwrap();
+        assert_eq!(reply.text, "\n");
+        assert_eq!(reply.values, vec![1003]);
+        assert_eq!(state, State::Done);
+    }
+}
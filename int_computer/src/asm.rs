@@ -0,0 +1,273 @@
+use crate::computer::{InstructionType, Mode};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    OperandCount { expected: usize, found: usize },
+    BadOperand(String),
+    BadValue(String),
+    BadLabel(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct AsmError {
+    pub kind: AsmErrorKind,
+    /// 1-based line number in the source.
+    pub line: usize,
+    pub text: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownMnemonic(m) => write!(f, "unknown mnemonic `{}`", m)?,
+            AsmErrorKind::OperandCount { expected, found } => {
+                write!(f, "expected {} operands, found {}", expected, found)?
+            }
+            AsmErrorKind::BadOperand(o) => write!(f, "invalid operand `{}`", o)?,
+            AsmErrorKind::BadValue(v) => write!(f, "invalid value `{}`", v)?,
+            AsmErrorKind::BadLabel(l) => write!(f, "invalid label name `{}`", l)?,
+            AsmErrorKind::DuplicateLabel(l) => write!(f, "label `{}` is already defined", l)?,
+            AsmErrorKind::UndefinedLabel(l) => write!(f, "undefined label `{}`", l)?,
+        }
+        write!(f, "\n    {}", self.text.trim())
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// A number, a label or a label with a constant offset.
+#[derive(Debug, Clone)]
+enum Value {
+    Number(i128),
+    Label(String, i128),
+}
+
+#[derive(Debug)]
+enum Statement {
+    Instruction(InstructionType, Vec<(Mode, Value)>),
+    Data(Vec<Value>),
+}
+
+pub fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    name != "rb" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if let Ok(n) = text.parse::<i128>() {
+        return Some(Value::Number(n));
+    }
+    let (label, offset) = match text.find(['+', '-']) {
+        Some(i) => {
+            let offset = text[i + 1..].trim().parse::<i128>().ok()?;
+            let sign = if text[i..].starts_with('-') { -1 } else { 1 };
+            (text[..i].trim(), sign * offset)
+        }
+        None => (text, 0),
+    };
+    if is_label(label) {
+        Some(Value::Label(label.to_string(), offset))
+    } else {
+        None
+    }
+}
+
+fn parse_operand(text: &str) -> Option<(Mode, Value)> {
+    let text = text.trim();
+    if text.starts_with('[') && text.ends_with(']') {
+        return parse_value(&text[1..text.len() - 1]).map(|v| (Mode::Position, v));
+    }
+    if let Some(rest) = text.strip_prefix('#') {
+        return parse_value(rest).map(|v| (Mode::Immediate, v));
+    }
+    if let Some(rest) = text.strip_prefix("rb") {
+        let rest = rest.trim();
+        return if rest.is_empty() {
+            Some((Mode::Relative, Value::Number(0)))
+        } else if let Some(offset) = rest.strip_prefix('+') {
+            parse_value(offset).map(|v| (Mode::Relative, v))
+        } else if rest.starts_with('-') {
+            match parse_value(rest)? {
+                Value::Number(n) => Some((Mode::Relative, Value::Number(n))),
+                Value::Label(..) => None,
+            }
+        } else {
+            None
+        };
+    }
+    None
+}
+
+fn parse_statement(code: &str) -> Result<Statement, AsmErrorKind> {
+    if let Some(rest) = code.strip_prefix(".data") {
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return Err(AsmErrorKind::UnknownMnemonic(code.to_string()));
+        }
+        return rest
+            .split(',')
+            .map(|v| parse_value(v).ok_or_else(|| AsmErrorKind::BadValue(v.trim().to_string())))
+            .collect::<Result<Vec<Value>, AsmErrorKind>>()
+            .map(Statement::Data);
+    }
+
+    let (mnemonic, rest) = match code.find(char::is_whitespace) {
+        Some(i) => (&code[..i], code[i..].trim()),
+        None => (code, ""),
+    };
+    let itype = InstructionType::from_mnemonic(mnemonic)
+        .ok_or_else(|| AsmErrorKind::UnknownMnemonic(mnemonic.to_string()))?;
+    let operands: Vec<&str> = if rest.is_empty() {
+        vec![]
+    } else {
+        rest.split(',').collect()
+    };
+    if operands.len() != itype.arity() {
+        return Err(AsmErrorKind::OperandCount {
+            expected: itype.arity(),
+            found: operands.len(),
+        });
+    }
+    operands
+        .iter()
+        .map(|o| parse_operand(o).ok_or_else(|| AsmErrorKind::BadOperand(o.trim().to_string())))
+        .collect::<Result<Vec<(Mode, Value)>, AsmErrorKind>>()
+        .map(|operands| Statement::Instruction(itype, operands))
+}
+
+/// Assembles Intcode source into a program that can be loaded with `Computer::new`.
+///
+/// Each line holds an optional `label:` followed by an instruction such as
+/// `add [x], #5, rb+3` or a `.data 1, 2, label` directive. Operands use `[addr]` for position,
+/// `#value` for immediate and `rb+offset` for relative mode; addresses and values can be numbers,
+/// labels or `label+offset`. Comments start with `;`.
+pub fn assemble(source: &str) -> Result<Vec<i128>, AsmError> {
+    let mut labels: HashMap<String, i128> = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = 0;
+
+    for (index, text) in source.lines().enumerate() {
+        let error = |kind| AsmError {
+            kind,
+            line: index + 1,
+            text: text.to_string(),
+        };
+        let mut code = text.split(';').next().unwrap_or("").trim();
+        if let Some(colon) = code.find(':') {
+            let label = code[..colon].trim();
+            if !is_label(label) {
+                return Err(error(AsmErrorKind::BadLabel(label.to_string())));
+            }
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(error(AsmErrorKind::DuplicateLabel(label.to_string())));
+            }
+            code = code[colon + 1..].trim();
+        }
+        if code.is_empty() {
+            continue;
+        }
+
+        let statement = parse_statement(code).map_err(error)?;
+        address += match &statement {
+            Statement::Instruction(_, operands) => 1 + operands.len() as i128,
+            Statement::Data(values) => values.len() as i128,
+        };
+        statements.push((index, statement));
+    }
+
+    let mut program = Vec::new();
+    for (index, statement) in statements {
+        let resolve = |value: &Value| match value {
+            Value::Number(n) => Ok(*n),
+            Value::Label(label, offset) => match labels.get(label) {
+                Some(address) => Ok(address + offset),
+                None => Err(AsmError {
+                    kind: AsmErrorKind::UndefinedLabel(label.clone()),
+                    line: index + 1,
+                    text: source.lines().nth(index).unwrap_or("").to_string(),
+                }),
+            },
+        };
+        match statement {
+            Statement::Instruction(itype, operands) => {
+                let modes = operands
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (mode, _))| mode.digit() * 10i128.pow(2 + i as u32))
+                    .sum::<i128>();
+                program.push(itype.code() + modes);
+                for (_, value) in &operands {
+                    program.push(resolve(value)?);
+                }
+            }
+            Statement::Data(values) => {
+                for value in &values {
+                    program.push(resolve(value)?);
+                }
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            ; day 7 example: out = in2 * 10 + in1
+                    in [phase]
+                    in [signal]
+                    mul [signal], #10, [signal]
+                    add [signal], [phase], [phase]
+                    out [phase]
+                    hlt
+            phase:  .data 0
+            signal: .data 0
+        ";
+        assert_eq!(
+            assemble(source),
+            Ok(vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0])
+        );
+    }
+
+    #[test]
+    fn test_assemble_operands() {
+        let source = "
+            start:
+                arb #table+1
+                jf rb-1, #start
+                out rb
+                .data table, -7
+            table:
+        ";
+        assert_eq!(assemble(source), Ok(vec![109, 10, 1206, -1, 0, 204, 0, 9, -7]));
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let kind = |source| assemble(source).unwrap_err().kind;
+        assert_eq!(kind("hlt\nfoo [1]"), AsmErrorKind::UnknownMnemonic("foo".to_string()));
+        assert_eq!(
+            kind("add [1], [2]"),
+            AsmErrorKind::OperandCount { expected: 3, found: 2 }
+        );
+        assert_eq!(kind("out {3}"), AsmErrorKind::BadOperand("{3}".to_string()));
+        assert_eq!(kind("a: hlt\na: hlt"), AsmErrorKind::DuplicateLabel("a".to_string()));
+        assert_eq!(kind("out [nowhere]"), AsmErrorKind::UndefinedLabel("nowhere".to_string()));
+        assert_eq!(assemble("\n\n  in #x").unwrap_err().line, 3);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::computer::{Computer, RunOptions, State, VmError};
+use crate::memory::{FlatMemory, Memory};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Instructions executed before handing control back to the executor, so that one busy
+/// machine does not starve the others sharing its thread.
+const SLICE: u64 = 10_000;
+
+#[derive(Debug)]
+pub enum AsyncError<E> {
+    Vm(VmError),
+    /// The output sink refused a value.
+    Sink(E),
+}
+
+impl<E: fmt::Display> fmt::Display for AsyncError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsyncError::Vm(err) => write!(f, "{}", err),
+            AsyncError::Sink(err) => write!(f, "cannot send output: {}", err),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for AsyncError<E> {}
+
+/// Returns `Pending` once, after asking to be polled again.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// Runs a `Computer` as a future: input is awaited from a stream and output is sent into a
+/// sink, so many machines can share a single-threaded executor.
+pub struct AsyncComputer<M: Memory = FlatMemory> {
+    computer: Computer<M>,
+}
+
+impl<M: Memory> AsyncComputer<M> {
+    pub fn new(computer: Computer<M>) -> AsyncComputer<M> {
+        AsyncComputer { computer }
+    }
+
+    pub fn computer(&self) -> &Computer<M> {
+        &self.computer
+    }
+
+    pub fn computer_mut(&mut self) -> &mut Computer<M> {
+        &mut self.computer
+    }
+
+    pub fn into_computer(self) -> Computer<M> {
+        self.computer
+    }
+
+    /// Runs until the program halts, returning `State::Done`, or until it needs input after
+    /// `input` has ended, returning `State::WaitingInput`.
+    ///
+    /// Values already queued with `add_input` are read before `input`, and every output is
+    /// sent as soon as it is produced.
+    pub async fn run<I, O>(
+        &mut self,
+        input: &mut I,
+        output: &mut O,
+    ) -> Result<State, AsyncError<O::Error>>
+    where
+        I: Stream<Item = M::Word> + Unpin,
+        O: Sink<M::Word> + Unpin,
+    {
+        let options = RunOptions::new().outputs(1).budget(SLICE);
+        loop {
+            let state = self.computer.run_with(&options).map_err(AsyncError::Vm)?;
+            for value in self.computer.get_all_output() {
+                output.send(value).await.map_err(AsyncError::Sink)?;
+            }
+            match state {
+                State::WaitingInput => match input.next().await {
+                    Some(value) => self.computer.add_input_word(value),
+                    None => return Ok(State::WaitingInput),
+                },
+                State::Done => return Ok(State::Done),
+                State::BudgetExhausted => YieldNow(false).await,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::mpsc;
+    use futures::executor::block_on;
+    use futures::future::join_all;
+
+    #[test]
+    fn test_feedback_loop() {
+        // day 7 example, amplifiers in a feedback loop
+        let program = [
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let phases = [9, 8, 7, 6, 5];
+        let (senders, receivers): (Vec<_>, Vec<_>) =
+            phases.iter().map(|_| mpsc::unbounded::<i128>()).unzip();
+        for (sender, &phase) in senders.iter().zip(&phases) {
+            sender.unbounded_send(phase).unwrap();
+        }
+        senders[0].unbounded_send(0).unwrap();
+
+        let amplifiers = receivers.into_iter().enumerate().map(|(i, mut input)| {
+            let mut output = senders[(i + 1) % senders.len()].clone();
+            let mut amplifier = AsyncComputer::new(Computer::new(&program));
+            async move {
+                assert_eq!(
+                    amplifier.run(&mut input, &mut output).await.unwrap(),
+                    State::Done
+                );
+                input
+            }
+        });
+        let mut inputs = block_on(join_all(amplifiers));
+        assert_eq!(inputs[0].try_recv().ok(), Some(139629729));
+    }
+
+    #[test]
+    fn test_input_ends() {
+        let mut amplifier = AsyncComputer::new(Computer::new(&[3, 9, 4, 9, 1105, 1, 0, 99, 0, 0]));
+        let mut output = Vec::new();
+        let mut input = futures::stream::iter(vec![4, 5]);
+        let state = block_on(amplifier.run(&mut input, &mut output)).unwrap();
+        assert_eq!(state, State::WaitingInput);
+        assert_eq!(output, vec![4, 5]);
+    }
+}
@@ -0,0 +1,226 @@
+use std::convert::TryFrom;
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::iter;
+
+extern crate int_computer;
+use int_computer::ascii::decode_output;
+use int_computer::computer::*;
+use int_computer::debugger::{Debugger, Stop};
+use int_computer::disasm::Item;
+
+/// Instructions the debugger can step back over.
+const HISTORY: usize = 1_000_000;
+
+const HELP: &str = "\
+commands:
+  s [n]          step n instructions (default 1)
+  c              continue until a breakpoint, watchpoint, input wait or halt
+  sb [n]         step back n instructions (default 1)
+  cb <addr>      step back until the instruction at addr is next
+  b <addr>       set a breakpoint          db <addr>  delete it
+  w <addr>       watch writes to addr      dw <addr>  delete it
+  i <v> [v...]   queue numeric input
+  a <text>       queue a line of ASCII input
+  o              print and clear pending output
+  r              show registers
+  x <addr> [n]   examine n memory words (default 8)
+  l [addr] [n]   list n instructions (default: 8 from the next instruction)
+  q              quit";
+
+fn print_stop(debugger: &Debugger, stop: &Stop) {
+    match stop {
+        Stop::Stepped => {}
+        Stop::Breakpoint(address) => println!("breakpoint at {}", address),
+        Stop::Watchpoint(write) => println!(
+            "watchpoint: [{}] {} -> {} (ip {})",
+            write.address,
+            write.old,
+            write.new,
+            debugger.computer().next_address()
+        ),
+        Stop::WaitingInput => println!("waiting for input"),
+        Stop::Done => println!("program halted"),
+    }
+}
+
+fn list(computer: &Computer, mut address: i128, count: usize) {
+    for _ in 0..count {
+        let (item, size) = match computer.decode_at(address) {
+            Ok(decoded) => {
+                let size = decoded.size() as i128;
+                (Item::Instruction(decoded), size)
+            }
+            Err(_) => (Item::Data(computer.memread(address)), 1),
+        };
+        let marker = if address == computer.next_address() {
+            "=>"
+        } else {
+            "  "
+        };
+        println!("{} {:>6}: {}", marker, address, item);
+        address = match address.checked_add(size) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+}
+
+fn parse_args(args: &[&str]) -> Result<Vec<i128>, String> {
+    args.iter()
+        .map(|a| a.parse::<i128>().map_err(|_| format!("not a number: {}", a)))
+        .collect()
+}
+
+/// An optional count argument, which must be positive.
+fn parse_count(arg: Option<&i128>, default: usize, usage: &str) -> Result<usize, String> {
+    match arg {
+        None => Ok(default),
+        Some(&count) if count > 0 => usize::try_from(count).map_err(|_| usage.to_string()),
+        Some(_) => Err(usage.to_string()),
+    }
+}
+
+fn execute(debugger: &mut Debugger, line: &str) -> Result<bool, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (command, args) = match words.split_first() {
+        Some((command, args)) => (*command, args),
+        None => return Ok(true),
+    };
+
+    match command {
+        "s" | "step" => {
+            let n = parse_args(args)?.first().cloned().unwrap_or(1);
+            for _ in 0..n {
+                let stop = debugger.step().map_err(|err| err.to_string())?;
+                if stop != Stop::Stepped {
+                    print_stop(debugger, &stop);
+                    break;
+                }
+            }
+            list(debugger.computer(), debugger.computer().next_address(), 1);
+        }
+        "c" | "continue" => {
+            let stop = debugger.run().map_err(|err| err.to_string())?;
+            print_stop(debugger, &stop);
+        }
+        "sb" => {
+            let n = parse_count(parse_args(args)?.first(), 1, "usage: sb [n], with n > 0")?;
+            let undone = debugger.computer_mut().step_back(n);
+            if undone < n {
+                println!("stepped back {} instructions, history exhausted", undone);
+            }
+            list(debugger.computer(), debugger.computer().next_address(), 1);
+        }
+        "cb" => {
+            let address = *parse_args(args)?.first().ok_or("missing address")?;
+            if !debugger.computer_mut().run_back_to(address) {
+                println!("{} not found in history", address);
+            }
+            list(debugger.computer(), debugger.computer().next_address(), 1);
+        }
+        "b" | "break" => {
+            for address in parse_args(args)? {
+                debugger.add_breakpoint(address);
+            }
+            println!("breakpoints: {:?}", debugger.breakpoints());
+        }
+        "db" => {
+            for address in parse_args(args)? {
+                debugger.remove_breakpoint(address);
+            }
+            println!("breakpoints: {:?}", debugger.breakpoints());
+        }
+        "w" | "watch" => {
+            for address in parse_args(args)? {
+                debugger.add_watchpoint(address);
+            }
+            println!("watchpoints: {:?}", debugger.watchpoints());
+        }
+        "dw" => {
+            for address in parse_args(args)? {
+                debugger.remove_watchpoint(address);
+            }
+            println!("watchpoints: {:?}", debugger.watchpoints());
+        }
+        "i" | "input" => {
+            for v in parse_args(args)? {
+                debugger.computer_mut().add_input_128(v);
+            }
+        }
+        "a" | "ascii" => {
+            let text = line.trim_start()[command.len()..].trim_start();
+            for c in text.chars() {
+                debugger.computer_mut().add_input_128(c as i128);
+            }
+            debugger.computer_mut().add_input(10);
+        }
+        "o" | "output" => {
+            let output = decode_output(&debugger.computer_mut().get_all_output());
+            print!("{}", output.text);
+            if !output.values.is_empty() {
+                println!("{:?}", output.values);
+            }
+        }
+        "r" | "regs" => {
+            let computer = debugger.computer();
+            println!(
+                "ip = {}  rb = {}",
+                computer.instruction_pointer(),
+                computer.relative_base()
+            );
+        }
+        "x" => {
+            let args = parse_args(args)?;
+            let address = *args.first().ok_or("missing address")?;
+            let count = parse_count(args.get(1), 8, "usage: x <addr> [n], with n > 0")?;
+            // stops at the end of the address range
+            let words: Vec<String> = iter::successors(Some(address), |pos| pos.checked_add(1))
+                .take(count)
+                .map(|pos| debugger.computer().memread(pos).to_string())
+                .collect();
+            println!("{:>6}: {}", address, words.join(" "));
+        }
+        "l" | "list" => {
+            let args = parse_args(args)?;
+            let computer = debugger.computer();
+            let address = args
+                .first()
+                .cloned()
+                .unwrap_or_else(|| computer.next_address());
+            let count = parse_count(args.get(1), 8, "usage: l [addr] [n], with n > 0")?;
+            list(computer, address, count);
+        }
+        "h" | "help" => println!("{}", HELP),
+        "q" | "quit" => return Ok(false),
+        _ => return Err(format!("unknown command `{}`, try `h`", command)),
+    }
+
+    Ok(true)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} <program file>", args[0]);
+        std::process::exit(1);
+    }
+    let computer = Computer::new(&load_program_or_exit(&args[1]));
+    let mut debugger = Debugger::new(computer.with_history(HISTORY));
+
+    list(debugger.computer(), 0, 1);
+    let stdin = io::stdin();
+    loop {
+        print!("(intdbg) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        match execute(&mut debugger, &line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => println!("error: {}", err),
+        }
+    }
+}
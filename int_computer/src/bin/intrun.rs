@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Write};
+
+extern crate int_computer;
+use int_computer::ascii::decode_output;
+use int_computer::computer::*;
+
+const USAGE: &str = "\
+usage: intrun [options] <program file>
+
+Runs an Intcode program, forwarding each line typed on stdin as ASCII input and
+printing its ASCII output. Values outside the ASCII range are printed as numbers.
+
+options:
+  -n, --numeric        read and print numbers instead of text; an input line holds
+                       one or more values separated by spaces or commas
+  -s, --script <file>  feed the lines of <file> before reading stdin (repeatable)
+  -l, --log <file>     write a transcript of the session to <file>
+  -p, --profile <file> write an execution profile to <file> when the program ends
+  -h, --help           show this message";
+
+struct Options {
+    program: String,
+    numeric: bool,
+    scripts: Vec<String>,
+    log: Option<String>,
+    profile: Option<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        program: String::new(),
+        numeric: false,
+        scripts: Vec::new(),
+        log: None,
+        profile: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" | "--numeric" => options.numeric = true,
+            "-s" | "--script" => options
+                .scripts
+                .push(args.next().ok_or("--script needs a file")?.clone()),
+            "-l" | "--log" => options.log = Some(args.next().ok_or("--log needs a file")?.clone()),
+            "-p" | "--profile" => {
+                options.profile = Some(args.next().ok_or("--profile needs a file")?.clone())
+            }
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ if options.program.is_empty() => options.program = arg.clone(),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    if options.program.is_empty() {
+        return Err("missing program file".to_string());
+    }
+    Ok(options)
+}
+
+/// Prints to stdout and copies to the transcript, if any.
+struct Console {
+    log: Option<File>,
+}
+
+impl Console {
+    fn print(&mut self, text: &str) {
+        print!("{}", text);
+        io::stdout().flush().unwrap();
+        if let Some(log) = self.log.as_mut() {
+            if let Err(err) = log.write_all(text.as_bytes()) {
+                eprintln!("Cannot write transcript: {}", err);
+                self.log = None;
+            }
+        }
+    }
+}
+
+/// Output as text, with values outside the ASCII range, the numeric answers of ASCII
+/// programs, on lines of their own after it.
+fn format_output(output: &[i128], numeric: bool) -> String {
+    let (mut text, values) = if numeric {
+        (String::new(), output.to_vec())
+    } else {
+        let decoded = decode_output(output);
+        (decoded.text, decoded.values)
+    };
+    for value in values {
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&format!("{}\n", value));
+    }
+    text
+}
+
+fn parse_numbers(line: &str) -> Result<Vec<i128>, String> {
+    line.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<i128>().map_err(|_| format!("not a number: {}", v)))
+        .collect()
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let options = parse_options(&args[1..]).unwrap_or_else(|err| {
+        if !err.is_empty() {
+            eprintln!("Error : {}", err);
+        }
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    });
+    let mut computer = Computer::new(&load_program_or_exit(&options.program));
+
+    let mut script: VecDeque<String> = VecDeque::new();
+    for file in &options.scripts {
+        let contents = fs::read_to_string(file).unwrap_or_else(|err| {
+            eprintln!("Error : {}", err);
+            eprintln!("Cannot read script {}", file);
+            std::process::exit(1);
+        });
+        script.extend(contents.lines().map(|line| line.to_string()));
+    }
+    let log = options.log.as_ref().map(|file| {
+        File::create(file).unwrap_or_else(|err| {
+            eprintln!("Error : {}", err);
+            eprintln!("Cannot create transcript {}", file);
+            std::process::exit(1);
+        })
+    });
+    let mut console = Console { log };
+    computer.set_profiling(options.profile.is_some());
+
+    let stdin = io::stdin();
+    loop {
+        let state = computer.run().unwrap_or_else(|err| {
+            eprintln!("Error : {}", err);
+            std::process::exit(1);
+        });
+        console.print(&format_output(&computer.get_all_output(), options.numeric));
+        if state == State::Done {
+            break;
+        }
+
+        let line = match script.pop_front() {
+            Some(line) => {
+                if options.numeric {
+                    console.print("> ");
+                }
+                console.print(&format!("{}\n", line));
+                line
+            }
+            None => {
+                if options.numeric {
+                    console.print("> ");
+                }
+                let mut line = String::new();
+                if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                    eprintln!("End of input while the program is waiting for input");
+                    break;
+                }
+                let line = line.trim_end_matches(&['\r', '\n'][..]).to_string();
+                if let Some(log) = console.log.as_mut() {
+                    let _ = writeln!(log, "{}", line);
+                }
+                line
+            }
+        };
+
+        if options.numeric {
+            match parse_numbers(&line) {
+                Ok(values) => values.into_iter().for_each(|v| computer.add_input_128(v)),
+                Err(err) => eprintln!("Error : {}", err),
+            }
+        } else {
+            for b in line.bytes() {
+                computer.add_input_128(b as i128);
+            }
+            computer.add_input(10);
+        }
+    }
+
+    if let (Some(file), Some(profile)) = (&options.profile, computer.profile()) {
+        if let Err(err) = fs::write(file, profile.report()) {
+            eprintln!("Error : {}", err);
+            eprintln!("Cannot write profile {}", file);
+            std::process::exit(1);
+        }
+    }
+}
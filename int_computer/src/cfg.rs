@@ -0,0 +1,493 @@
+use crate::computer::{Decoded, InstructionType, Mode};
+use crate::disasm::{decode_at, Item};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// How control leaves a basic block.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum BlockEnd {
+    /// Runs into the next block, which starts at a jump target.
+    Fallthrough,
+    /// Conditional jump to an immediate target.
+    Branch,
+    /// Unconditional jump to an immediate target.
+    Jump,
+    /// Unconditional jump after storing the address following it on the stack.
+    Call,
+    /// Unconditional jump through a relative-mode slot, where calls leave the return address,
+    /// or through any slot right after an `arb #-N` tears down a stack frame.
+    Return,
+    /// Jump to an address read from memory that is not a return.
+    Indirect,
+    Halt,
+    /// The next word does not decode, or lies past the end of the program.
+    Invalid,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum EdgeKind {
+    Fallthrough,
+    Taken,
+    NotTaken,
+    Call,
+    /// From a call to the instruction after it, where the callee returns to.
+    CallReturn,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Edge {
+    pub to: i128,
+    pub kind: EdgeKind,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Block {
+    pub start: i128,
+    /// One past the last word of the block.
+    pub end: i128,
+    pub instructions: Vec<Decoded>,
+    pub exit: BlockEnd,
+    pub edges: Vec<Edge>,
+}
+
+/// A call target with the blocks reached from it without entering other calls.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Function {
+    pub entry: i128,
+    pub blocks: Vec<i128>,
+    /// Size of the stack frame the function opens with `arb #N` on entry and closes with
+    /// `arb #-N` before each of its returns, if it follows that pattern.
+    pub frame: Option<i128>,
+}
+
+/// Control flow graph of a program as it is loaded, found by following fallthroughs and
+/// immediate jump targets from address 0. Code only reached through computed jumps, or
+/// written by the program itself, is not discovered.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Cfg {
+    blocks: BTreeMap<i128, Block>,
+}
+
+/// Value an immediate operand always has, `None` for any other mode.
+fn constant(decoded: &Decoded, param: usize) -> Option<i128> {
+    match decoded.modes()[param] {
+        Mode::Immediate => Some(decoded.params()[param]),
+        _ => None,
+    }
+}
+
+/// The constant an `add` or `mul` of two immediates stores in a relative-mode slot, which is
+/// how calls push their return address.
+fn pushed_constant(decoded: &Decoded) -> Option<i128> {
+    let operation: fn(i128, i128) -> Option<i128> = match decoded.itype() {
+        InstructionType::Add => i128::checked_add,
+        InstructionType::Multiply => i128::checked_mul,
+        _ => return None,
+    };
+    if decoded.modes()[2] != Mode::Relative {
+        return None;
+    }
+    operation(constant(decoded, 0)?, constant(decoded, 1)?)
+}
+
+/// The constant an `arb` moves the relative base by, if it is one.
+fn frame_change(decoded: &Decoded) -> Option<i128> {
+    match decoded.itype() {
+        InstructionType::AdjustBase => constant(decoded, 0),
+        _ => None,
+    }
+}
+
+/// How the instruction passes control on; an empty edge list ends the path. `torn_down` is
+/// whether the instruction before it on the path was an `arb #-N`.
+fn exits(decoded: &Decoded, pushed: &[i128], torn_down: bool) -> Option<(BlockEnd, Vec<Edge>)> {
+    let next = decoded.address() + decoded.size() as i128;
+    let jump_if = match decoded.itype() {
+        InstructionType::JumpIfTrue => true,
+        InstructionType::JumpIfFalse => false,
+        InstructionType::Exit => return Some((BlockEnd::Halt, vec![])),
+        _ => return None,
+    };
+    let always = constant(decoded, 0).map(|c| (c != 0) == jump_if);
+    let edge = |to, kind| Edge { to, kind };
+    Some(match (always, constant(decoded, 1)) {
+        // never taken, just a slow no-op
+        (Some(false), _) => return None,
+        (Some(true), Some(target)) if pushed.contains(&next) => (
+            BlockEnd::Call,
+            vec![
+                edge(target, EdgeKind::Call),
+                edge(next, EdgeKind::CallReturn),
+            ],
+        ),
+        (Some(true), Some(target)) => (BlockEnd::Jump, vec![edge(target, EdgeKind::Taken)]),
+        (None, Some(target)) => (
+            BlockEnd::Branch,
+            vec![
+                edge(target, EdgeKind::Taken),
+                edge(next, EdgeKind::NotTaken),
+            ],
+        ),
+        (Some(true), None) if decoded.modes()[1] == Mode::Relative || torn_down => {
+            (BlockEnd::Return, vec![])
+        }
+        (Some(true), None) => (BlockEnd::Indirect, vec![]),
+        (None, None) => (BlockEnd::Indirect, vec![edge(next, EdgeKind::NotTaken)]),
+    })
+}
+
+/// Graphviz id of the block at `address`; ids cannot hold a minus sign.
+fn node(address: i128) -> String {
+    if address < 0 {
+        format!("bm{}", address.unsigned_abs())
+    } else {
+        format!("b{}", address)
+    }
+}
+
+impl Cfg {
+    pub fn build(program: &[i128]) -> Cfg {
+        // first pass: every reachable instruction, how it exits and where blocks must start
+        let mut instructions: BTreeMap<i128, Decoded> = BTreeMap::new();
+        let mut terminators: BTreeMap<i128, (BlockEnd, Vec<Edge>)> = BTreeMap::new();
+        let mut leaders: BTreeSet<i128> = BTreeSet::new();
+        leaders.insert(0);
+        let in_program = |address: i128| (0..program.len() as i128).contains(&address);
+        let mut work: Vec<(i128, Vec<i128>, bool)> = vec![(0, vec![], false)];
+        while let Some((address, mut pushed, torn_down)) = work.pop() {
+            if instructions.contains_key(&address) || !in_program(address) {
+                continue;
+            }
+            let decoded = match decode_at(program, address as usize) {
+                Some(decoded) => decoded,
+                None => continue,
+            };
+            instructions.insert(address, decoded);
+            match exits(&decoded, &pushed, torn_down) {
+                Some((end, edges)) => {
+                    // targets outside the program are exits, not blocks
+                    for edge in edges.iter().filter(|e| in_program(e.to)) {
+                        leaders.insert(edge.to);
+                        work.push((edge.to, vec![], false));
+                    }
+                    terminators.insert(address, (end, edges));
+                }
+                None => {
+                    pushed.extend(pushed_constant(&decoded));
+                    let torn_down = frame_change(&decoded).is_some_and(|n| n < 0);
+                    work.push((address + decoded.size() as i128, pushed, torn_down));
+                }
+            }
+        }
+
+        // second pass: cut the instructions into blocks at leaders and terminators
+        let mut blocks = BTreeMap::new();
+        for &start in &leaders {
+            let mut address = start;
+            let mut block = Block {
+                start,
+                end: start,
+                instructions: Vec::new(),
+                exit: BlockEnd::Invalid,
+                edges: Vec::new(),
+            };
+            while let Some(decoded) = instructions.get(&address) {
+                block.instructions.push(*decoded);
+                address += decoded.size() as i128;
+                if let Some((end, edges)) = terminators.get(&decoded.address()) {
+                    block.exit = *end;
+                    block.edges = edges.clone();
+                    break;
+                }
+                if leaders.contains(&address) {
+                    block.exit = BlockEnd::Fallthrough;
+                    block.edges = vec![Edge {
+                        to: address,
+                        kind: EdgeKind::Fallthrough,
+                    }];
+                    break;
+                }
+            }
+            block.end = address;
+            if !block.instructions.is_empty() {
+                blocks.insert(start, block);
+            }
+        }
+        Cfg { blocks }
+    }
+
+    /// Blocks in address order.
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    pub fn block(&self, start: i128) -> Option<&Block> {
+        self.blocks.get(&start)
+    }
+
+    /// The block holding the instruction that starts at or covers `address`.
+    pub fn block_containing(&self, address: i128) -> Option<&Block> {
+        self.blocks
+            .range(..=address)
+            .next_back()
+            .map(|(_, block)| block)
+            .filter(|block| address < block.end)
+    }
+
+    /// Call targets, each with the blocks it runs up to its returns, in address order.
+    pub fn functions(&self) -> Vec<Function> {
+        let entries: BTreeSet<i128> = self
+            .blocks()
+            .flat_map(|b| b.edges.iter())
+            .filter(|e| e.kind == EdgeKind::Call)
+            .map(|e| e.to)
+            .collect();
+        entries
+            .into_iter()
+            .map(|entry| {
+                let mut seen = BTreeSet::new();
+                let mut work = vec![entry];
+                while let Some(start) = work.pop() {
+                    if let Some(block) = self.blocks.get(&start) {
+                        if seen.insert(start) {
+                            let local = block.edges.iter().filter(|e| e.kind != EdgeKind::Call);
+                            work.extend(local.map(|e| e.to));
+                        }
+                    }
+                }
+                let blocks: Vec<i128> = seen.into_iter().collect();
+                Function {
+                    entry,
+                    frame: self.frame(entry, &blocks),
+                    blocks,
+                }
+            })
+            .collect()
+    }
+
+    /// The frame a function at `entry` made of `blocks` opens and closes around its returns.
+    fn frame(&self, entry: i128, blocks: &[i128]) -> Option<i128> {
+        let first = self.blocks.get(&entry)?.instructions.first()?;
+        let size = frame_change(first).filter(|&n| n > 0)?;
+        let returns = blocks
+            .iter()
+            .filter_map(|start| self.blocks.get(start))
+            .filter(|b| b.exit == BlockEnd::Return);
+        let closes = |block: &Block| {
+            block
+                .instructions
+                .iter()
+                .any(|i| frame_change(i) == Some(-size))
+        };
+        let mut returns = returns.peekable();
+        if returns.peek().is_some() && returns.all(closes) {
+            Some(size)
+        } else {
+            None
+        }
+    }
+
+    /// The graph in Graphviz format, one box per block listing its instructions. Calls are
+    /// dashed, call returns dotted and function entries drawn with a double border. Targets
+    /// that do not decode get a dashed placeholder box.
+    pub fn to_dot(&self) -> String {
+        let entries: BTreeSet<i128> = self.functions().iter().map(|f| f.entry).collect();
+        let mut dot =
+            String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in self.blocks() {
+            let mut label = String::new();
+            for decoded in &block.instructions {
+                let _ = write!(
+                    label,
+                    "{}: {}\\l",
+                    decoded.address(),
+                    Item::Instruction(*decoded)
+                );
+            }
+            match block.exit {
+                BlockEnd::Return => label.push_str("(return)\\l"),
+                BlockEnd::Indirect => label.push_str("(indirect jump)\\l"),
+                BlockEnd::Invalid => label.push_str("(invalid)\\l"),
+                _ => {}
+            }
+            let border = if entries.contains(&block.start) {
+                ", peripheries=2"
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                dot,
+                "    {} [label=\"{}\"{}];",
+                node(block.start),
+                label,
+                border
+            );
+        }
+        let missing: BTreeSet<i128> = self
+            .blocks()
+            .flat_map(|b| b.edges.iter())
+            .map(|e| e.to)
+            .filter(|to| !self.blocks.contains_key(to))
+            .collect();
+        for to in missing {
+            let _ = writeln!(dot, "    {} [label=\"{}: ?\", style=dashed];", node(to), to);
+        }
+        for block in self.blocks() {
+            for edge in &block.edges {
+                let style = match (edge.kind, block.exit) {
+                    (EdgeKind::Taken, BlockEnd::Branch) => " [label=\"T\"]",
+                    (EdgeKind::NotTaken, _) => " [label=\"F\"]",
+                    (EdgeKind::Call, _) => " [style=dashed, label=\"call\"]",
+                    (EdgeKind::CallReturn, _) => " [style=dotted]",
+                    _ => "",
+                };
+                let from = node(block.start);
+                let _ = writeln!(dot, "    {} -> {}{};", from, node(edge.to), style);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    fn program() -> Vec<i128> {
+        assemble(
+            "
+                    arb #100
+                    in [n]
+            loop:   add #back, #0, rb+0
+                    jt #1, #double
+            back:   out [n]
+                    jf [n], #done
+                    jt #1, #loop
+            done:   hlt
+            double: arb #1
+                    mul [n], #2, [n]
+                    arb #-1
+                    jt #1, rb+0
+            n:      .data 0
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_blocks() {
+        let cfg = Cfg::build(&program());
+        let summary: Vec<(i128, i128, BlockEnd)> =
+            cfg.blocks().map(|b| (b.start, b.end, b.exit)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, 4, BlockEnd::Fallthrough),
+                (4, 11, BlockEnd::Call),
+                (11, 16, BlockEnd::Branch),
+                (16, 19, BlockEnd::Jump),
+                (19, 20, BlockEnd::Halt),
+                (20, 31, BlockEnd::Return),
+            ]
+        );
+        let edge = |to, kind| Edge { to, kind };
+        assert_eq!(
+            cfg.block(4).unwrap().edges,
+            vec![edge(20, EdgeKind::Call), edge(11, EdgeKind::CallReturn)]
+        );
+        assert_eq!(
+            cfg.block(11).unwrap().edges,
+            vec![edge(19, EdgeKind::Taken), edge(16, EdgeKind::NotTaken)]
+        );
+        assert_eq!(cfg.block_containing(24).map(|b| b.start), Some(20));
+        assert_eq!(cfg.block_containing(31), None);
+        assert_eq!(
+            cfg.functions(),
+            vec![Function {
+                entry: 20,
+                blocks: vec![20],
+                frame: Some(1),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_dot() {
+        let dot = Cfg::build(&program()).to_dot();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("    b0 [label=\"0: arb #100\\l2: in [31]\\l\"];\n"));
+        assert!(dot.contains("    b20 [label=\"20: arb #1\\l"));
+        assert!(dot.contains("(return)\\l\", peripheries=2];\n"));
+        assert!(dot.contains("    b0 -> b4;\n"));
+        assert!(dot.contains("    b4 -> b20 [style=dashed, label=\"call\"];\n"));
+        assert!(dot.contains("    b4 -> b11 [style=dotted];\n"));
+        assert!(dot.contains("    b11 -> b19 [label=\"T\"];\n    b11 -> b16 [label=\"F\"];\n"));
+        assert!(dot.ends_with("    b16 -> b4;\n}\n"));
+    }
+
+    #[test]
+    fn test_stack_frames() {
+        // the caller pushes a return address and an argument above the relative base; the
+        // callee opens a frame over them, squares the argument in place and tears it down
+        let program = assemble(
+            "
+                    arb #100
+                    in [n]
+                    add #back, #0, rb+1
+                    add [n], #0, rb+2
+                    jt #1, #square
+            back:   out rb+2
+                    jf [n], #-5
+                    jt #1, #far
+                    hlt
+            square: arb #3
+                    mul rb-1, rb-1, rb-1
+                    arb #-3
+                    jt #1, rb-2
+            far:    arb #2
+                    arb #-2
+                    jt #1, [n]
+            n:      .data 0
+            ",
+        )
+        .unwrap();
+        let cfg = Cfg::build(&program);
+        let back = 15;
+        assert_eq!(cfg.block(0).map(|b| b.exit), Some(BlockEnd::Call));
+        assert_eq!(
+            cfg.block(0).unwrap().edges[1],
+            Edge {
+                to: back,
+                kind: EdgeKind::CallReturn
+            }
+        );
+        let functions = cfg.functions();
+        assert_eq!(functions.len(), 1);
+        assert_eq!(functions[0].frame, Some(3));
+        let square = functions[0].entry;
+        assert_eq!(cfg.block(square).map(|b| b.exit), Some(BlockEnd::Return));
+
+        // a jump through memory right after the frame is torn down is a return as well
+        let far = cfg.blocks().last().unwrap();
+        assert_eq!(far.exit, BlockEnd::Return);
+
+        // the jump to -5 cannot be decoded and gets a placeholder node
+        let dot = cfg.to_dot();
+        assert!(dot.contains("    bm5 [label=\"-5: ?\", style=dashed];\n"));
+        assert!(dot.contains(" -> bm5 [label=\"T\"];\n"));
+    }
+
+    #[test]
+    fn test_targets_outside_program() {
+        for &target in &[1 << 64, 4, -1, i128::MIN] {
+            let cfg = Cfg::build(&[1105, 1, target, 99]);
+            let blocks: Vec<i128> = cfg.blocks().map(|b| b.start).collect();
+            assert_eq!(blocks, vec![0], "jump to {}", target);
+            assert_eq!(cfg.block(0).unwrap().edges[0].to, target);
+            let placeholder = format!("[label=\"{}: ?\", style=dashed];", target);
+            assert!(cfg.to_dot().contains(&placeholder), "jump to {}", target);
+        }
+    }
+}
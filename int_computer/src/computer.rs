@@ -0,0 +1,1402 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::history::History;
+use crate::io::{Input, Output};
+use crate::memory::{FlatMemory, Memory};
+use crate::opcode::{Exec, Flow, Opcode, Opcodes};
+use crate::profile::Profile;
+use crate::protect::{Guard, MemoryFault, MemoryPolicy};
+use crate::trace::{TraceEvent, Tracer};
+use crate::word::{Overflow, Word};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum InstructionType {
+    Add,
+    Multiply,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustBase,
+    Exit,
+    /// An opcode added with `Computer::register_opcode`.
+    Custom {
+        code: u8,
+        arity: usize,
+        mnemonic: &'static str,
+    },
+}
+
+impl InstructionType {
+    pub fn from_opcode(opcode: i128) -> Option<InstructionType> {
+        use InstructionType::*;
+        match opcode % 100 {
+            1 => Some(Add),
+            2 => Some(Multiply),
+            3 => Some(Input),
+            4 => Some(Output),
+            5 => Some(JumpIfTrue),
+            6 => Some(JumpIfFalse),
+            7 => Some(LessThan),
+            8 => Some(Equals),
+            9 => Some(AdjustBase),
+            99 => Some(Exit),
+            _ => None,
+        }
+    }
+
+    /// Opcode of the instruction with every parameter in position mode.
+    pub fn code(&self) -> i128 {
+        use InstructionType::*;
+        match self {
+            Add => 1,
+            Multiply => 2,
+            Input => 3,
+            Output => 4,
+            JumpIfTrue => 5,
+            JumpIfFalse => 6,
+            LessThan => 7,
+            Equals => 8,
+            AdjustBase => 9,
+            Exit => 99,
+            Custom { code, .. } => *code as i128,
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        use InstructionType::*;
+        match self {
+            Add | Multiply | LessThan | Equals => 3,
+            JumpIfTrue | JumpIfFalse => 2,
+            Input | Output | AdjustBase => 1,
+            Exit => 0,
+            Custom { arity, .. } => *arity,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        use InstructionType::*;
+        match self {
+            Add => "add",
+            Multiply => "mul",
+            Input => "in",
+            Output => "out",
+            JumpIfTrue => "jt",
+            JumpIfFalse => "jf",
+            LessThan => "lt",
+            Equals => "eq",
+            AdjustBase => "arb",
+            Exit => "hlt",
+            Custom { mnemonic, .. } => mnemonic,
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<InstructionType> {
+        use InstructionType::*;
+        [
+            Add,
+            Multiply,
+            Input,
+            Output,
+            JumpIfTrue,
+            JumpIfFalse,
+            LessThan,
+            Equals,
+            AdjustBase,
+            Exit,
+        ]
+        .iter()
+        .find(|t| t.mnemonic() == mnemonic)
+        .cloned()
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl Mode {
+    pub fn from_digit(digit: i128) -> Option<Mode> {
+        match digit {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
+        }
+    }
+
+    pub fn digit(self) -> i128 {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+}
+
+/// Largest number of parameters taken by any instruction.
+pub const MAX_PARAMS: usize = 3;
+
+/// An instruction as laid out in memory, before its parameters are resolved to addresses.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Decoded {
+    itype: InstructionType,
+    modes: [Mode; MAX_PARAMS],
+    params: [i128; MAX_PARAMS],
+    address: i128,
+    opcode: i128,
+}
+
+impl Decoded {
+    pub fn itype(&self) -> InstructionType {
+        self.itype
+    }
+
+    pub fn modes(&self) -> &[Mode] {
+        &self.modes[..self.itype.arity()]
+    }
+
+    /// Raw parameter words following the opcode.
+    pub fn params(&self) -> &[i128] {
+        &self.params[..self.itype.arity()]
+    }
+
+    pub fn address(&self) -> i128 {
+        self.address
+    }
+
+    pub fn opcode(&self) -> i128 {
+        self.opcode
+    }
+
+    /// Number of memory words the instruction occupies.
+    pub fn size(&self) -> usize {
+        1 + self.itype.arity()
+    }
+}
+
+/// Decodes the instruction at `address`, reading memory through `read`.
+pub fn decode<F: Fn(i128) -> i128>(read: F, address: i128) -> Result<Decoded, VmError> {
+    decode_with(read, address, InstructionType::from_opcode)
+}
+
+/// Like `decode`, with `lookup` telling which instruction an opcode stands for.
+pub fn decode_with<F, L>(read: F, address: i128, lookup: L) -> Result<Decoded, VmError>
+where
+    F: Fn(i128) -> i128,
+    L: Fn(i128) -> Option<InstructionType>,
+{
+    let opcode = read(address);
+    let itype = match lookup(opcode) {
+        Some(itype) => itype,
+        None => return Err(VmError::BadOpcode { ip: address, opcode }),
+    };
+
+    let mut modes = [Mode::Position; MAX_PARAMS];
+    let mut params = [0; MAX_PARAMS];
+    let mut op = opcode / 100;
+    for i in 0..itype.arity() {
+        match Mode::from_digit(op % 10) {
+            Some(mode) => modes[i] = mode,
+            None => {
+                return Err(VmError::BadMode {
+                    ip: address,
+                    opcode,
+                    mode: op % 10,
+                })
+            }
+        }
+        params[i] = read(address + 1 + i as i128);
+        op /= 10;
+    }
+
+    Ok(Decoded {
+        itype,
+        modes,
+        params,
+        address,
+        opcode,
+    })
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum State {
+    WaitingInput,
+    Done,
+    /// The number of outputs asked for with `RunOptions::outputs` has been produced.
+    Output,
+    /// The instruction budget of `RunOptions::budget` ran out.
+    BudgetExhausted,
+    /// The next instruction to execute is on a breakpoint.
+    Breakpoint(i128),
+}
+
+/// Extra reasons for `Computer::run_with` to return before the program needs input or halts.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct RunOptions {
+    outputs: Option<usize>,
+    budget: Option<u64>,
+    breakpoints: BTreeSet<i128>,
+}
+
+impl RunOptions {
+    pub fn new() -> RunOptions {
+        RunOptions::default()
+    }
+
+    /// Returns `State::Output` once `n` values have been output during the call.
+    pub fn outputs(mut self, n: usize) -> RunOptions {
+        self.outputs = Some(n);
+        self
+    }
+
+    /// Returns `State::BudgetExhausted` once `n` instructions have executed during the call.
+    pub fn budget(mut self, n: u64) -> RunOptions {
+        self.budget = Some(n);
+        self
+    }
+
+    /// Returns `State::Breakpoint` before executing the instruction at `address`. A breakpoint
+    /// on the first instruction of the call is skipped so that running again moves past it.
+    pub fn breakpoint(mut self, address: i128) -> RunOptions {
+        self.breakpoints.insert(address);
+        self
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct MemoryWrite<W = i128> {
+    pub address: i128,
+    pub old: W,
+    pub new: W,
+}
+
+/// Outcome of executing a single instruction with `Computer::step`.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Step<W = i128> {
+    Executed {
+        address: i128,
+        /// Every word the instruction wrote, in write order.
+        writes: Vec<MemoryWrite<W>>,
+    },
+    WaitingInput,
+    Done,
+}
+
+#[derive(Debug)]
+pub enum VmError {
+    BadOpcode { ip: i128, opcode: i128 },
+    BadMode { ip: i128, opcode: i128, mode: i128 },
+    NegativeAddress { ip: i128, opcode: i128, address: i128 },
+    /// The result of an arithmetic instruction does not fit in a word, see `Overflow::Checked`.
+    Overflow { ip: i128, opcode: i128 },
+    /// A memory access was refused by the `MemoryPolicy`.
+    Fault { ip: i128, opcode: i128, fault: MemoryFault },
+    /// An address, jump target or relative base does not fit in an `i128`.
+    AddressOutOfRange { ip: i128, opcode: i128 },
+    Io(std::io::Error),
+    Parse(ParseError),
+}
+
+impl VmError {
+    /// Address of the instruction that faulted, if the error happened while executing.
+    pub fn ip(&self) -> Option<i128> {
+        match self {
+            VmError::BadOpcode { ip, .. }
+            | VmError::BadMode { ip, .. }
+            | VmError::NegativeAddress { ip, .. }
+            | VmError::Overflow { ip, .. }
+            | VmError::Fault { ip, .. }
+            | VmError::AddressOutOfRange { ip, .. } => Some(*ip),
+            VmError::Io(_) | VmError::Parse(_) => None,
+        }
+    }
+
+    /// Raw opcode word of the instruction that faulted, modes included.
+    pub fn opcode(&self) -> Option<i128> {
+        match self {
+            VmError::BadOpcode { opcode, .. }
+            | VmError::BadMode { opcode, .. }
+            | VmError::NegativeAddress { opcode, .. }
+            | VmError::Overflow { opcode, .. }
+            | VmError::Fault { opcode, .. }
+            | VmError::AddressOutOfRange { opcode, .. } => Some(*opcode),
+            VmError::Io(_) | VmError::Parse(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::BadOpcode { ip, opcode } => {
+                write!(f, "unknown opcode {} at address {}", opcode, ip)
+            }
+            VmError::BadMode { ip, opcode, mode } => write!(
+                f,
+                "unknown parameter mode {} in opcode {} at address {}",
+                mode, opcode, ip
+            ),
+            VmError::NegativeAddress { ip, opcode, address } => write!(
+                f,
+                "access to negative address {} by opcode {} at address {}",
+                address, opcode, ip
+            ),
+            VmError::Overflow { ip, opcode } => write!(
+                f,
+                "arithmetic overflow in opcode {} at address {}",
+                opcode, ip
+            ),
+            VmError::Fault { ip, opcode, fault } => write!(
+                f,
+                "memory fault in opcode {} at address {}: {}",
+                opcode, ip, fault
+            ),
+            VmError::AddressOutOfRange { ip, opcode } => write!(
+                f,
+                "address out of range in opcode {} at address {}",
+                opcode, ip
+            ),
+            VmError::Io(err) => write!(f, "i/o error: {}", err),
+            VmError::Parse(err) => write!(f, "parse error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for VmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VmError::Io(err) => Some(err),
+            VmError::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for VmError {
+    fn from(err: std::io::Error) -> VmError {
+        VmError::Io(err)
+    }
+}
+
+impl From<ParseError> for VmError {
+    fn from(err: ParseError) -> VmError {
+        VmError::Parse(err)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Instruction {
+    pub(crate) itype: InstructionType,
+    pub(crate) operands: [i128; MAX_PARAMS],
+    pub(crate) address: i128,
+    pub(crate) opcode: i128,
+}
+
+impl Instruction {
+    pub fn itype(&self) -> InstructionType {
+        self.itype
+    }
+
+    /// Memory positions of the parameters, after applying their modes.
+    pub fn operands(&self) -> &[i128] {
+        &self.operands[..self.itype.arity()]
+    }
+
+    pub fn address(&self) -> i128 {
+        self.address
+    }
+
+    pub fn opcode(&self) -> i128 {
+        self.opcode
+    }
+}
+
+pub struct Computer<M: Memory = FlatMemory> {
+    memory: M,
+    output: VecDeque<M::Word>,
+    input: VecDeque<M::Word>,
+    pub(crate) instruction_pointer: i128,
+    last_instr: Option<Instruction>,
+    pub(crate) relative_base: i128,
+    tracer: Option<Box<dyn Tracer<M::Word> + Send>>,
+    cache: Option<Vec<Option<Decoded>>>,
+    source: Option<Box<dyn Input<M::Word> + Send>>,
+    sink: Option<Box<dyn Output<M::Word> + Send>>,
+    opcodes: Arc<Opcodes<M>>,
+    overflow: Overflow,
+    output_count: u64,
+    input_count: u64,
+    image_end: i128,
+    guard: Option<Guard>,
+    profile: Option<Profile>,
+    history: Option<History<M::Word>>,
+}
+
+/// Saved machine state of a `Computer`, see `Computer::snapshot`.
+#[derive(Clone, Debug)]
+pub struct Snapshot<M: Memory = FlatMemory> {
+    pub(crate) memory: M,
+    pub(crate) output: VecDeque<M::Word>,
+    pub(crate) input: VecDeque<M::Word>,
+    pub(crate) instruction_pointer: i128,
+    pub(crate) last_instr: Option<Instruction>,
+    pub(crate) relative_base: i128,
+}
+
+/// Clones the machine state, the instruction cache and the registered opcodes; an installed
+/// tracer, input source, output sink, profile or undo log is not carried over.
+impl<M: Memory + Clone> Clone for Computer<M> {
+    fn clone(&self) -> Computer<M> {
+        Computer {
+            memory: self.memory.clone(),
+            output: self.output.clone(),
+            input: self.input.clone(),
+            instruction_pointer: self.instruction_pointer,
+            last_instr: self.last_instr,
+            relative_base: self.relative_base,
+            tracer: None,
+            cache: self.cache.clone(),
+            source: None,
+            sink: None,
+            opcodes: self.opcodes.clone(),
+            overflow: self.overflow,
+            output_count: 0,
+            input_count: 0,
+            image_end: self.image_end,
+            guard: self.guard.clone(),
+            profile: None,
+            history: None,
+        }
+    }
+}
+
+/// Addresses at or above this are never cached, to bound the size of the cache.
+const CACHE_LIMIT: i128 = 1 << 20;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ParseErrorKind {
+    InvalidNumber,
+    EmptyValue,
+    MissingSeparator,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// Index of the offending value in the program, i.e. how many values were parsed before it.
+    pub index: usize,
+    /// Byte offset of the offending token in the source text.
+    pub offset: usize,
+    pub token: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self.kind {
+            ParseErrorKind::InvalidNumber => "invalid value",
+            ParseErrorKind::EmptyValue => "missing value before",
+            ParseErrorKind::MissingSeparator => "missing ',' before",
+        };
+        write!(
+            f,
+            "{} `{}` at value {} (byte offset {})",
+            what, self.token, self.index, self.offset
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn is_comment_start(c: char) -> bool {
+    c == '#' || c == ';'
+}
+
+/// Reads and parses the Intcode program in `filename`, see `parse_program`.
+pub fn load_program<P: AsRef<Path>>(filename: P) -> Result<Vec<i128>, VmError> {
+    let file_contents = std::fs::read_to_string(filename)?;
+    Ok(parse_program(&file_contents)?)
+}
+
+/// Loads the program in `filename` for a command-line tool, exiting with a message if it
+/// cannot. Hosts that must keep running use `load_program` instead.
+pub fn load_program_or_exit(filename: &str) -> Vec<i128> {
+    load_program(filename).unwrap_or_else(|err| {
+        eprintln!("Error : {}", err);
+        eprintln!("Cannot load program from file {}", filename);
+        std::process::exit(1);
+    })
+}
+
+/// Parses the text of an Intcode program, the same as `parse_program`.
+pub fn read_instructions(input: &str) -> Result<Vec<i128>, ParseError> {
+    parse_program(input)
+}
+
+/// Parses a comma separated Intcode program.
+///
+/// Whitespace and newlines around values are ignored, a single trailing comma is allowed
+/// and `#` or `;` start a comment that runs to the end of the line.
+pub fn parse_program(input: &str) -> Result<Vec<i128>, ParseError> {
+    let mut program = Vec::new();
+    let mut expecting_value = true;
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(offset, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if is_comment_start(c) {
+            for (_, c) in chars.by_ref() {
+                if c == '\n' {
+                    break;
+                }
+            }
+        } else if c == ',' {
+            if expecting_value {
+                return Err(ParseError {
+                    kind: ParseErrorKind::EmptyValue,
+                    index: program.len(),
+                    offset,
+                    token: ",".to_string(),
+                });
+            }
+            chars.next();
+            expecting_value = true;
+        } else {
+            let mut end = offset;
+            while let Some(&(i, c)) = chars.peek() {
+                if c == ',' || c.is_whitespace() || is_comment_start(c) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let token = &input[offset..end];
+            let error = |kind| ParseError {
+                kind,
+                index: program.len(),
+                offset,
+                token: token.to_string(),
+            };
+            if !expecting_value {
+                return Err(error(ParseErrorKind::MissingSeparator));
+            }
+            match token.parse::<i128>() {
+                Ok(v) => program.push(v),
+                Err(_) => return Err(error(ParseErrorKind::InvalidNumber)),
+            }
+            expecting_value = false;
+        }
+    }
+
+    Ok(program)
+}
+
+impl Computer {
+    pub fn new(p: &[i128]) -> Computer {
+        Computer::with_memory(FlatMemory::from_program(p))
+    }
+    pub fn new32(p: &[i32]) -> Computer {
+        Computer::new(&p.iter().map(|x| *x as i128).collect::<Vec<i128>>())
+    }
+
+    pub fn new_from_file<P: AsRef<Path>>(filename: P) -> Result<Computer, VmError> {
+        Ok(Computer::new(&load_program(filename)?))
+    }
+}
+
+impl<M: Memory> Computer<M> {
+    /// Creates a computer running the program already loaded in `memory`.
+    pub fn with_memory(memory: M) -> Computer<M> {
+        Computer {
+            image_end: memory.end(),
+            memory,
+            output: VecDeque::new(),
+            input: VecDeque::new(),
+            instruction_pointer: 0,
+            last_instr: None,
+            relative_base: 0,
+            tracer: None,
+            cache: None,
+            source: None,
+            sink: None,
+            opcodes: Arc::new(Opcodes::builtin()),
+            overflow: Overflow::default(),
+            output_count: 0,
+            input_count: 0,
+            guard: None,
+            profile: None,
+            history: None,
+        }
+    }
+
+    /// Creates a computer in the state captured by `snapshot`, without a tracer or cache and
+    /// with only the built-in opcodes; custom ones must be registered again.
+    pub fn from_snapshot(snapshot: Snapshot<M>) -> Computer<M> {
+        Computer {
+            image_end: snapshot.memory.end(),
+            memory: snapshot.memory,
+            output: snapshot.output,
+            input: snapshot.input,
+            instruction_pointer: snapshot.instruction_pointer,
+            last_instr: snapshot.last_instr,
+            relative_base: snapshot.relative_base,
+            tracer: None,
+            cache: None,
+            source: None,
+            sink: None,
+            opcodes: Arc::new(Opcodes::builtin()),
+            overflow: Overflow::default(),
+            output_count: 0,
+            input_count: 0,
+            guard: None,
+            profile: None,
+            history: None,
+        }
+    }
+
+    /// Captures memory, queues, registers and any input instruction still waiting for a value.
+    pub fn snapshot(&self) -> Snapshot<M>
+    where
+        M: Clone,
+    {
+        Snapshot {
+            memory: self.memory.clone(),
+            output: self.output.clone(),
+            input: self.input.clone(),
+            instruction_pointer: self.instruction_pointer,
+            last_instr: self.last_instr,
+            relative_base: self.relative_base,
+        }
+    }
+
+    /// Puts the machine back in the state captured by `snapshot`. The tracer, the registered
+    /// opcodes, the memory policy and the instruction cache setting are kept; the undo log is
+    /// emptied.
+    pub fn restore(&mut self, snapshot: &Snapshot<M>)
+    where
+        M: Clone,
+    {
+        self.memory = snapshot.memory.clone();
+        self.output = snapshot.output.clone();
+        self.input = snapshot.input.clone();
+        self.instruction_pointer = snapshot.instruction_pointer;
+        self.last_instr = snapshot.last_instr;
+        self.relative_base = snapshot.relative_base;
+        if let Some(cache) = self.cache.as_mut() {
+            cache.clear();
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+    }
+
+    /// Installs a tracer that is called after every executed instruction.
+    pub fn set_tracer<T: Tracer<M::Word> + Send + 'static>(&mut self, tracer: T) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer<M::Word> + Send>> {
+        self.tracer.take()
+    }
+
+    /// Reads input from `source` whenever the input queue is empty.
+    pub fn set_input_source<I: Input<M::Word> + Send + 'static>(&mut self, source: I) {
+        self.source = Some(Box::new(source));
+    }
+
+    pub fn take_input_source(&mut self) -> Option<Box<dyn Input<M::Word> + Send>> {
+        self.source.take()
+    }
+
+    pub fn with_input_source<I>(mut self, source: I) -> Computer<M>
+    where
+        I: Input<M::Word> + Send + 'static,
+    {
+        self.set_input_source(source);
+        self
+    }
+
+    /// Sends output to `sink` instead of the output queue.
+    pub fn set_output_sink<O: Output<M::Word> + Send + 'static>(&mut self, sink: O) {
+        self.sink = Some(Box::new(sink));
+    }
+
+    pub fn take_output_sink(&mut self) -> Option<Box<dyn Output<M::Word> + Send>> {
+        self.sink.take()
+    }
+
+    pub fn with_output_sink<O>(mut self, sink: O) -> Computer<M>
+    where
+        O: Output<M::Word> + Send + 'static,
+    {
+        self.set_output_sink(sink);
+        self
+    }
+
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    /// Adds an opcode or replaces the one with the same code, returning the replaced opcode.
+    pub fn register_opcode(&mut self, opcode: Opcode<M>) -> Option<Opcode<M>> {
+        if let Some(cache) = self.cache.as_mut() {
+            cache.clear();
+        }
+        Arc::make_mut(&mut self.opcodes).insert(opcode)
+    }
+
+    pub fn opcodes(&self) -> &Opcodes<M> {
+        &self.opcodes
+    }
+
+    /// Chooses what `add` and `mul` do on overflow; `Overflow::Checked` unless set.
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    /// Turns on counting of executed instructions, jumps, writes and I/O, see `Profile`.
+    /// Turning it off drops the profile gathered so far.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profile = if enabled { Some(Profile::new()) } else { None };
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Returns the profile gathered so far and starts a new one.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.as_mut().map(std::mem::take)
+    }
+
+    /// Restricts the memory accesses of the program. The program image protected by
+    /// `MemoryPolicy::protect_image` is the memory the computer was created with.
+    pub fn set_memory_policy(&mut self, policy: MemoryPolicy) {
+        self.guard = Some(Guard::new(policy, self.image_end));
+    }
+
+    pub fn with_memory_policy(mut self, policy: MemoryPolicy) -> Computer<M> {
+        self.set_memory_policy(policy);
+        self
+    }
+
+    pub fn clear_memory_policy(&mut self) {
+        self.guard = None;
+    }
+
+    pub fn memory_policy(&self) -> Option<&MemoryPolicy> {
+        self.guard.as_ref().map(|g| g.policy())
+    }
+
+    /// Turns on the undo log, keeping what the last `steps` executed instructions changed so
+    /// that `step_back` and `run_back_to` can undo them. Output already produced is not taken
+    /// back, and the counters of an active profile or memory policy are left as they are.
+    pub fn set_history(&mut self, steps: usize) {
+        self.history = Some(History::new(steps));
+    }
+
+    pub fn with_history(mut self, steps: usize) -> Computer<M> {
+        self.set_history(steps);
+        self
+    }
+
+    /// Turns off the undo log and forgets what it holds.
+    pub fn clear_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Maximum number of steps kept by the undo log, if it is on.
+    pub fn history_limit(&self) -> Option<usize> {
+        self.history.as_ref().map(|h| h.limit())
+    }
+
+    /// Number of executed instructions that can currently be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, |h| h.len())
+    }
+
+    /// Undoes the last `n` executed instructions, restoring the memory they wrote, the
+    /// instruction pointer, the relative base and the input they consumed, which is put back
+    /// at the front of the input queue. Returns how many were undone, fewer than `n` once the
+    /// undo log runs out.
+    pub fn step_back(&mut self, n: usize) -> usize {
+        (0..n).take_while(|_| self.undo()).count()
+    }
+
+    /// Undoes executed instructions until the next one to run is at `address`, undoing at
+    /// least one. Returns false, with everything in the undo log undone, if it is not found.
+    pub fn run_back_to(&mut self, address: i128) -> bool {
+        while self.undo() {
+            if self.next_address() == address {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn undo(&mut self) -> bool {
+        let entry = match self.history.as_mut().and_then(|h| h.pop()) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        for (address, old) in entry.writes.into_iter().rev() {
+            self.memwrite(address, old);
+        }
+        if let Some(value) = entry.input {
+            self.input.push_front(value);
+        }
+        self.instruction_pointer = entry.instruction_pointer;
+        self.relative_base = entry.relative_base;
+        self.last_instr = entry.last_instr;
+        true
+    }
+
+    /// Address of the instruction the next `step` executes, which may be an input
+    /// instruction still waiting for a value. Unlike `instruction_pointer`, which already
+    /// points past an input instruction once it waits, this is what a user sees as current.
+    pub fn next_address(&self) -> i128 {
+        self.last_instr
+            .map_or(self.instruction_pointer, |instr| instr.address)
+    }
+
+    fn lookup(&self, opcode: i128) -> Option<InstructionType> {
+        self.opcodes.get(opcode).map(|o| o.itype())
+    }
+
+    /// Decodes the instruction at `address` with the opcodes registered on this computer.
+    ///
+    /// Only immediate parameters may hold words outside the `i128` range; they decode as
+    /// `i128::MAX` or `i128::MIN`, but execution reads the full word from memory.
+    pub fn decode_at(&self, address: i128) -> Result<Decoded, VmError> {
+        let word = |pos| self.memread(pos).to_i128();
+        let clamped = |pos| {
+            word(pos).unwrap_or_else(|| {
+                if self.memread(pos) < M::Word::default() {
+                    i128::MIN
+                } else {
+                    i128::MAX
+                }
+            })
+        };
+        let decoded = decode_with(clamped, address, |opcode| self.lookup(opcode))?;
+        if word(address).is_none() {
+            return Err(VmError::BadOpcode {
+                ip: address,
+                opcode: decoded.opcode,
+            });
+        }
+        let out_of_range = (0..decoded.itype.arity()).any(|i| {
+            decoded.modes[i] != Mode::Immediate && word(address + 1 + i as i128).is_none()
+        });
+        if out_of_range {
+            return Err(VmError::AddressOutOfRange {
+                ip: address,
+                opcode: decoded.opcode,
+            });
+        }
+        Ok(decoded)
+    }
+
+    /// Turns on caching of decoded instructions by address, so hot loops skip re-decoding.
+    /// Writes to memory drop any cached instruction they overlap.
+    pub fn set_instruction_cache(&mut self, enabled: bool) {
+        self.cache = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    pub fn memwrite(&mut self, pos: i128, value: M::Word) {
+        self.memory.write(pos, value);
+        if let Some(cache) = self.cache.as_mut() {
+            let first = pos.saturating_sub(MAX_PARAMS as i128).max(0);
+            for address in first..=pos.min(cache.len() as i128 - 1) {
+                cache[address as usize] = None;
+            }
+        }
+    }
+
+    pub fn memread(&self, pos: i128) -> M::Word {
+        self.memory.read(pos)
+    }
+
+    fn is_valid_mem(&self, pos: i128) -> bool {
+        pos >= 0 && pos < self.memory.end()
+    }
+
+    pub(crate) fn load(&self, instr: &Instruction, pos: i128) -> Result<M::Word, VmError> {
+        if pos < 0 {
+            return Err(VmError::NegativeAddress {
+                ip: instr.address,
+                opcode: instr.opcode,
+                address: pos,
+            });
+        }
+        if let Some(guard) = &self.guard {
+            guard.check_read(pos).map_err(|fault| VmError::Fault {
+                ip: instr.address,
+                opcode: instr.opcode,
+                fault,
+            })?;
+        }
+        Ok(self.memread(pos))
+    }
+
+    pub(crate) fn store(
+        &mut self,
+        instr: &Instruction,
+        pos: i128,
+        value: M::Word,
+    ) -> Result<MemoryWrite<M::Word>, VmError> {
+        if pos < 0 {
+            return Err(VmError::NegativeAddress {
+                ip: instr.address,
+                opcode: instr.opcode,
+                address: pos,
+            });
+        }
+        if let Some(guard) = &self.guard {
+            let end = self.memory.end();
+            guard.check_write(pos, end).map_err(|fault| VmError::Fault {
+                ip: instr.address,
+                opcode: instr.opcode,
+                fault,
+            })?;
+        }
+        let old = self.memread(pos);
+        if let Some(history) = self.history.as_mut() {
+            history.write(pos, old.clone());
+        }
+        self.memwrite(pos, value.clone());
+        Ok(MemoryWrite {
+            address: pos,
+            old,
+            new: value,
+        })
+    }
+
+    pub fn instruction_pointer(&self) -> i128 {
+        self.instruction_pointer
+    }
+
+    pub fn relative_base(&self) -> i128 {
+        self.relative_base
+    }
+
+    /// Whether the input queue holds values; the input source is not consulted.
+    pub fn has_input(&self) -> bool {
+        !self.input.is_empty()
+    }
+
+    pub fn add_input(&mut self, v: i32) {
+        self.input.push_back(M::Word::from(v));
+    }
+
+    /// # Panics
+    ///
+    /// If `v` does not fit in the word type.
+    pub fn add_input_128(&mut self, v: i128) {
+        let word = M::Word::from_i128(v).expect("input does not fit in a word");
+        self.input.push_back(word);
+    }
+
+    pub fn add_input_word(&mut self, v: M::Word) {
+        self.input.push_back(v);
+    }
+
+    pub fn get_output(&mut self) -> Option<M::Word> {
+        self.output.pop_front()
+    }
+
+    pub fn get_all_output(&mut self) -> Vec<M::Word> {
+        self.output.drain(..).collect()
+    }
+
+    pub fn get_exit_value(&mut self) -> Option<M::Word> {
+        self.output.pop_back()
+    }
+
+    /// Pops the next input value, asking the input source when the queue is empty.
+    pub(crate) fn take_input(&mut self) -> Option<M::Word> {
+        self.input
+            .pop_front()
+            .or_else(|| self.source.as_mut().and_then(|s| s.read()))
+    }
+
+    pub(crate) fn put_output(&mut self, value: M::Word) {
+        self.output_count += 1;
+        match self.sink.as_mut() {
+            Some(sink) => sink.write(value),
+            None => self.output.push_back(value),
+        }
+    }
+
+    fn resolve(&self, decoded: &Decoded) -> Result<[i128; MAX_PARAMS], VmError> {
+        let out_of_range = || VmError::AddressOutOfRange {
+            ip: decoded.address,
+            opcode: decoded.opcode,
+        };
+        let mut operands = [0; MAX_PARAMS];
+        for (i, operand) in operands.iter_mut().enumerate().take(decoded.itype.arity()) {
+            let param = decoded.params[i];
+            *operand = match decoded.modes[i] {
+                Mode::Position => param,
+                Mode::Immediate => decoded.address + 1 + i as i128,
+                Mode::Relative => self
+                    .relative_base
+                    .checked_add(param)
+                    .ok_or_else(out_of_range)?,
+            };
+        }
+        Ok(operands)
+    }
+
+    fn cached(&self, address: i128) -> Option<Decoded> {
+        match &self.cache {
+            Some(cache) if address >= 0 && address < cache.len() as i128 => cache[address as usize],
+            _ => None,
+        }
+    }
+
+    fn next_instruction(&mut self) -> Result<Instruction, VmError> {
+        if let Some(i) = self.last_instr.take() {
+            return Ok(i);
+        }
+
+        let ip = self.instruction_pointer;
+        let decoded = match self.cached(ip) {
+            Some(decoded) => decoded,
+            None => {
+                let decoded = self.decode_at(ip)?;
+                if let Some(cache) = self.cache.as_mut() {
+                    if (0..CACHE_LIMIT).contains(&ip) {
+                        if ip as usize >= cache.len() {
+                            cache.resize(ip as usize + 1, None);
+                        }
+                        cache[ip as usize] = Some(decoded);
+                    }
+                }
+                decoded
+            }
+        };
+        let operands = self.resolve(&decoded)?;
+        self.instruction_pointer += decoded.size() as i128;
+
+        Ok(Instruction {
+            operands,
+            itype: decoded.itype,
+            address: decoded.address,
+            opcode: decoded.opcode,
+        })
+    }
+
+    /// Executes a single instruction.
+    ///
+    /// On error the instruction pointer is left on the faulting instruction and nothing is written.
+    pub fn step(&mut self) -> Result<Step<M::Word>, VmError> {
+        if self.last_instr.is_none() && !self.is_valid_mem(self.instruction_pointer) {
+            return Ok(Step::Done);
+        }
+        let before = (self.instruction_pointer, self.relative_base, self.last_instr);
+        let instr = self.next_instruction()?;
+        let address = instr.address;
+        let traced = if self.tracer.is_some() {
+            Some((instr, self.operand_values(&instr)))
+        } else {
+            None
+        };
+        let counts = (self.input_count, self.output_count);
+        let step = self.execute(instr).inspect_err(|_| {
+            self.instruction_pointer = address;
+        });
+        if let Some(history) = self.history.as_mut() {
+            match &step {
+                Ok(Step::Executed { .. }) => history.commit(before.0, before.1, before.2),
+                _ => history.discard(),
+            }
+        }
+        let step = step?;
+
+        if let Some(profile) = self.profile.as_mut() {
+            let (next, written) = match &step {
+                Step::Executed { writes, .. } => (
+                    Some(self.instruction_pointer),
+                    writes.iter().map(|w| w.address).max(),
+                ),
+                _ => (None, None),
+            };
+            if step != Step::WaitingInput {
+                let inputs = self.input_count - counts.0;
+                let outputs = self.output_count - counts.1;
+                profile.record(&instr, next, written, inputs, outputs);
+            }
+        }
+
+        if let (Some(tracer), Some((instruction, values))) = (self.tracer.as_mut(), traced) {
+            let writes = match &step {
+                Step::Executed { writes, .. } => writes.clone(),
+                _ => Vec::new(),
+            };
+            if step != Step::WaitingInput {
+                tracer.trace(&TraceEvent {
+                    address,
+                    instruction,
+                    values,
+                    writes,
+                });
+            }
+        }
+        Ok(step)
+    }
+
+    fn operand_values(&self, instr: &Instruction) -> Vec<M::Word> {
+        instr.operands().iter().map(|&pos| self.memread(pos)).collect()
+    }
+
+    fn execute(&mut self, instr: Instruction) -> Result<Step<M::Word>, VmError> {
+        let opcode: Opcode<M> = match self.opcodes.get(instr.opcode) {
+            Some(opcode) => *opcode,
+            None => {
+                return Err(VmError::BadOpcode {
+                    ip: instr.address,
+                    opcode: instr.opcode,
+                })
+            }
+        };
+        let mut exec = Exec {
+            computer: self,
+            instruction: instr,
+            writes: opcode.writes(),
+            written: Vec::new(),
+            taken: None,
+        };
+        let flow = (opcode.handler())(&mut exec);
+        let (written, taken) = (exec.written, exec.taken);
+        if flow.is_err() {
+            // a handler failing after some of its writes leaves memory as it found it
+            for write in written.iter().rev() {
+                self.memwrite(write.address, write.old.clone());
+            }
+        }
+        match (&flow, taken) {
+            // a value is only consumed by an instruction that completes
+            (Err(_) | Ok(Flow::WaitInput), Some(value)) => self.input.push_front(value),
+            (Ok(_), Some(value)) => {
+                self.input_count += 1;
+                if let Some(history) = self.history.as_mut() {
+                    history.consume(value);
+                }
+            }
+            _ => {}
+        }
+
+        match flow? {
+            Flow::Continue => Ok(Step::Executed {
+                address: instr.address,
+                writes: written,
+            }),
+            Flow::WaitInput => {
+                self.last_instr = Some(instr);
+                Ok(Step::WaitingInput)
+            }
+            Flow::Halt => {
+                // stay on the instruction so that running again keeps reporting Done
+                self.instruction_pointer = instr.address;
+                Ok(Step::Done)
+            }
+        }
+    }
+
+    /// Runs until the program needs input or halts.
+    pub fn run(&mut self) -> Result<State, VmError> {
+        loop {
+            match self.step()? {
+                Step::Executed { .. } => {}
+                Step::WaitingInput => return Ok(State::WaitingInput),
+                Step::Done => return Ok(State::Done),
+            }
+        }
+    }
+
+    /// Like `run`, but also stops for the reasons selected in `options`.
+    pub fn run_with(&mut self, options: &RunOptions) -> Result<State, VmError> {
+        let mut executed = 0;
+        let first_output = self.output_count;
+        loop {
+            let ip = self.next_address();
+            if executed > 0 && options.breakpoints.contains(&ip) {
+                return Ok(State::Breakpoint(ip));
+            }
+            if options.budget.is_some_and(|budget| executed >= budget) {
+                return Ok(State::BudgetExhausted);
+            }
+            match self.step()? {
+                Step::Executed { .. } => {}
+                Step::WaitingInput => return Ok(State::WaitingInput),
+                Step::Done => return Ok(State::Done),
+            }
+            executed += 1;
+            let outputs = (self.output_count - first_output) as usize;
+            if options.outputs.is_some_and(|n| outputs >= n) {
+                return Ok(State::Output);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_program() {
+        assert_eq!(parse_program("1,0,0,3,99\n"), Ok(vec![1, 0, 0, 3, 99]));
+        assert_eq!(parse_program(" 1, -2 ,\n 3,"), Ok(vec![1, -2, 3]));
+        assert_eq!(
+            parse_program("# header\n1,2, ; first\n3 # last\n"),
+            Ok(vec![1, 2, 3])
+        );
+        assert_eq!(parse_program(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_parse_program_errors() {
+        let err = parse_program("1,2,x3,4").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidNumber);
+        assert_eq!((err.index, err.offset, err.token.as_str()), (2, 4, "x3"));
+
+        let err = parse_program("1,\n,2").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::EmptyValue);
+        assert_eq!((err.index, err.offset), (1, 3));
+
+        let err = parse_program("1,2 3").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::MissingSeparator);
+        assert_eq!((err.index, err.offset), (2, 4));
+    }
+
+    #[test]
+    fn test_load_program() {
+        let path = std::env::temp_dir().join(format!("load_program_{}", std::process::id()));
+        std::fs::write(&path, "1,0,0,3,99\n").unwrap();
+        assert_eq!(load_program(&path).unwrap(), vec![1, 0, 0, 3, 99]);
+        std::fs::write(&path, "1,0,0,3,\n,99\n").unwrap();
+        assert!(matches!(load_program(&path), Err(VmError::Parse(_))));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(load_program(&path), Err(VmError::Io(_))));
+
+        assert_eq!(read_instructions("1,0,0,3,99\n"), Ok(vec![1, 0, 0, 3, 99]));
+    }
+
+    #[test]
+    fn test_instruction_cache_self_modifying() {
+        let program = crate::asm::assemble(
+            "
+            print:  out #0
+                    add [print+1], #1, [print+1]
+                    lt [print+1], #3, [flag]
+                    jt [flag], #print
+                    mul #1, #99, [print]
+                    jt #1, #print
+            flag:   .data 0
+            ",
+        )
+        .unwrap();
+        for &cached in &[false, true] {
+            let mut computer = Computer::new(&program);
+            computer.set_instruction_cache(cached);
+            assert_eq!(computer.run().unwrap(), State::Done);
+            assert_eq!(computer.get_all_output(), vec![0, 1, 2]);
+            assert_eq!(computer.instruction_pointer(), 0);
+        }
+
+        // writes at the far ends of the address range
+        let mut computer = Computer::new(&program);
+        computer.set_instruction_cache(true);
+        for &pos in &[i128::MIN, -1, i128::MAX] {
+            computer.memwrite(pos, 5);
+            assert_eq!(computer.memread(pos), 5);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        // doubles each input until it reads 0
+        let program = crate::asm::assemble(
+            "
+            loop:   in [value]
+                    jf [value], #end
+                    mul [value], #2, [value]
+                    out [value]
+                    jt #1, #loop
+            end:    hlt
+            value:  .data 0
+            ",
+        )
+        .unwrap();
+        let mut computer = Computer::new(&program);
+        computer.add_input(1);
+        assert_eq!(computer.run().unwrap(), State::WaitingInput);
+        let snapshot = computer.snapshot();
+        let mut branch = computer.clone();
+
+        computer.add_input(5);
+        computer.run().unwrap();
+        assert_eq!(computer.get_all_output(), vec![2, 10]);
+
+        branch.add_input(0);
+        assert_eq!(branch.run().unwrap(), State::Done);
+        assert_eq!(branch.get_all_output(), vec![2]);
+
+        computer.restore(&snapshot);
+        assert_eq!(computer.get_output(), Some(2));
+        computer.add_input(7);
+        computer.run().unwrap();
+        assert_eq!(computer.get_all_output(), vec![14]);
+    }
+
+    #[test]
+    fn test_run_with() {
+        // counts up from 1 forever
+        let program = crate::asm::assemble(
+            "
+            loop:   add [n], #1, [n]
+                    out [n]
+                    jt #1, #loop
+            n:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut computer = Computer::new(&program);
+        let options = RunOptions::new().outputs(2);
+        assert_eq!(computer.run_with(&options).unwrap(), State::Output);
+        assert_eq!(computer.run_with(&options).unwrap(), State::Output);
+        assert_eq!(computer.get_all_output(), vec![1, 2, 3, 4]);
+
+        let options = RunOptions::new().budget(7);
+        assert_eq!(computer.run_with(&options).unwrap(), State::BudgetExhausted);
+        assert_eq!(computer.get_all_output(), vec![5, 6]);
+        assert_eq!(computer.instruction_pointer(), 0);
+
+        let options = RunOptions::new().breakpoint(6).budget(100);
+        assert_eq!(computer.run_with(&options).unwrap(), State::Breakpoint(6));
+        assert_eq!(computer.get_all_output(), vec![7]);
+
+        let program = [3, 0, 4, 0, 99];
+        let mut computer = Computer::new(&program);
+        assert_eq!(computer.run_with(&options).unwrap(), State::WaitingInput);
+        assert_eq!(computer.instruction_pointer(), 2);
+        assert_eq!(computer.next_address(), 0);
+        computer.add_input(3);
+        assert_eq!(computer.run_with(&options).unwrap(), State::Done);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::computer::{Computer, MemoryWrite, Step, VmError};
+use crate::memory::{FlatMemory, Memory};
+use std::collections::BTreeSet;
+
+/// Why the debugger handed control back.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Stop<W = i128> {
+    /// A single step completed without hitting a watchpoint.
+    Stepped,
+    Breakpoint(i128),
+    Watchpoint(MemoryWrite<W>),
+    WaitingInput,
+    Done,
+}
+
+pub struct Debugger<M: Memory = FlatMemory> {
+    computer: Computer<M>,
+    breakpoints: BTreeSet<i128>,
+    watchpoints: BTreeSet<i128>,
+}
+
+impl<M: Memory> Debugger<M> {
+    pub fn new(computer: Computer<M>) -> Debugger<M> {
+        Debugger {
+            computer,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn computer(&self) -> &Computer<M> {
+        &self.computer
+    }
+
+    pub fn computer_mut(&mut self) -> &mut Computer<M> {
+        &mut self.computer
+    }
+
+    pub fn into_computer(self) -> Computer<M> {
+        self.computer
+    }
+
+    pub fn add_breakpoint(&mut self, address: i128) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    pub fn remove_breakpoint(&mut self, address: i128) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<i128> {
+        &self.breakpoints
+    }
+
+    pub fn add_watchpoint(&mut self, address: i128) -> bool {
+        self.watchpoints.insert(address)
+    }
+
+    pub fn remove_watchpoint(&mut self, address: i128) -> bool {
+        self.watchpoints.remove(&address)
+    }
+
+    pub fn watchpoints(&self) -> &BTreeSet<i128> {
+        &self.watchpoints
+    }
+
+    /// Executes one instruction; breakpoints are ignored. An instruction writing to several
+    /// watched addresses stops on the first of those writes.
+    pub fn step(&mut self) -> Result<Stop<M::Word>, VmError> {
+        Ok(match self.computer.step()? {
+            Step::Executed { writes, .. } => {
+                let watched = writes
+                    .into_iter()
+                    .find(|w| self.watchpoints.contains(&w.address));
+                watched.map_or(Stop::Stepped, Stop::Watchpoint)
+            }
+            Step::WaitingInput => Stop::WaitingInput,
+            Step::Done => Stop::Done,
+        })
+    }
+
+    /// Runs until a breakpoint or watchpoint is hit, the program needs input or it halts.
+    ///
+    /// A breakpoint on the instruction about to execute is skipped, so calling `run` again
+    /// after stopping on a breakpoint moves past it.
+    pub fn run(&mut self) -> Result<Stop<M::Word>, VmError> {
+        let mut first = true;
+        loop {
+            let ip = self.computer.next_address();
+            if !first && self.breakpoints.contains(&ip) {
+                return Ok(Stop::Breakpoint(ip));
+            }
+            first = false;
+            match self.step()? {
+                Stop::Stepped => {}
+                stop => return Ok(stop),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    fn counter() -> Computer {
+        let program = assemble(
+            "
+                    in [limit]
+            loop:   add [count], #1, [count]
+                    lt [count], [limit], [flag]
+                    jt [flag], #loop
+                    out [count]
+                    hlt
+            count:  .data 0
+            limit:  .data 0
+            flag:   .data 0
+            ",
+        )
+        .unwrap();
+        Computer::new(&program)
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = Debugger::new(counter());
+        debugger.add_breakpoint(2);
+        assert_eq!(debugger.run().unwrap(), Stop::WaitingInput);
+        debugger.computer_mut().add_input(3);
+        assert_eq!(debugger.step().unwrap(), Stop::Stepped);
+        assert_eq!(debugger.computer().instruction_pointer(), 2);
+        assert_eq!(debugger.run().unwrap(), Stop::Breakpoint(2));
+        assert_eq!(debugger.computer().memread(16), 1);
+        assert_eq!(debugger.run().unwrap(), Stop::Breakpoint(2));
+        assert_eq!(debugger.computer().memread(16), 2);
+        assert!(debugger.remove_breakpoint(2));
+        assert_eq!(debugger.run().unwrap(), Stop::Done);
+        assert_eq!(debugger.computer_mut().get_output(), Some(3));
+    }
+
+    #[test]
+    fn test_watchpoint_on_second_write() {
+        use crate::opcode::{Exec, Flow, Opcode};
+        fn swap(exec: &mut Exec<FlatMemory>) -> Result<Flow, VmError> {
+            let (a, b) = (exec.read(0)?, exec.read(1)?);
+            exec.write(0, b)?;
+            exec.write(1, a)?;
+            Ok(Flow::Continue)
+        }
+        let mut computer = Computer::new(&[20, 7, 8, 99, 0, 0, 0, 1, 2]);
+        computer.register_opcode(Opcode::new(20, "swp", 2, &[0, 1], swap));
+        let mut debugger = Debugger::new(computer);
+        debugger.add_watchpoint(8);
+        let write = MemoryWrite {
+            address: 8,
+            old: 2,
+            new: 1,
+        };
+        assert_eq!(debugger.run().unwrap(), Stop::Watchpoint(write));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut debugger = Debugger::new(counter());
+        debugger.computer_mut().add_input(2);
+        debugger.add_watchpoint(16);
+        let write = MemoryWrite {
+            address: 16,
+            old: 0,
+            new: 1,
+        };
+        assert_eq!(debugger.run().unwrap(), Stop::Watchpoint(write));
+        assert_eq!(debugger.computer().instruction_pointer(), 6);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::computer::{decode, Decoded, InstructionType, Mode};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Item {
+    Instruction(Decoded),
+    Data(i128),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Line {
+    pub address: usize,
+    pub words: Vec<i128>,
+    pub item: Item,
+}
+
+pub fn format_operand(mode: Mode, param: i128) -> String {
+    match mode {
+        Mode::Position => format!("[{}]", param),
+        Mode::Immediate => format!("#{}", param),
+        Mode::Relative if param < 0 => format!("rb{}", param),
+        Mode::Relative => format!("rb+{}", param),
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Item::Instruction(decoded) => {
+                let operands: Vec<String> = decoded
+                    .modes()
+                    .iter()
+                    .zip(decoded.params().iter())
+                    .map(|(mode, param)| format_operand(*mode, *param))
+                    .collect();
+                if operands.is_empty() {
+                    write!(f, "{}", decoded.itype().mnemonic())
+                } else {
+                    write!(f, "{} {}", decoded.itype().mnemonic(), operands.join(", "))
+                }
+            }
+            Item::Data(value) => write!(f, ".data {}", value),
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words: Vec<String> = self.words.iter().map(|w| w.to_string()).collect();
+        write!(f, "{:>6}: {:<24} {}", self.address, words.join(","), self.item)
+    }
+}
+
+/// Decodes the instruction at `address` if it fits in the program and its opcode has no digits
+/// beyond the parameter modes, so that it can be written back exactly as it was read.
+pub(crate) fn decode_at(program: &[i128], address: usize) -> Option<Decoded> {
+    let read = |pos: i128| program.get(pos as usize).cloned().unwrap_or(0);
+    let decoded = decode(read, address as i128).ok()?;
+    let mode_digits = 10i128.pow(2 + decoded.params().len() as u32);
+    if address + decoded.size() > program.len() || decoded.opcode() / mode_digits != 0 {
+        return None;
+    }
+    Some(decoded)
+}
+
+/// Walks the program from address 0, decoding one instruction after the other.
+/// Words that do not form a valid instruction are emitted as `.data`.
+pub fn disassemble(program: &[i128]) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < program.len() {
+        let (item, size) = match decode_at(program, address) {
+            Some(decoded) => {
+                let size = decoded.size();
+                (Item::Instruction(decoded), size)
+            }
+            None => (Item::Data(program[address]), 1),
+        };
+        lines.push(Line {
+            address,
+            words: program[address..address + size].to_vec(),
+            item,
+        });
+        address += size;
+    }
+    lines
+}
+
+pub fn listing(program: &[i128]) -> String {
+    disassemble(program)
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+fn jump_target(decoded: &Decoded) -> Option<i128> {
+    match decoded.itype() {
+        InstructionType::JumpIfTrue | InstructionType::JumpIfFalse
+            if decoded.modes()[1] == Mode::Immediate =>
+        {
+            Some(decoded.params()[1])
+        }
+        _ => None,
+    }
+}
+
+/// Turns a program back into source accepted by `asm::assemble`.
+/// Immediate jump targets that land on a decoded instruction are given an `L<address>` label.
+pub fn to_source(program: &[i128]) -> String {
+    let lines = disassemble(program);
+    let starts: Vec<i128> = lines
+        .iter()
+        .filter(|line| matches!(line.item, Item::Instruction(_)))
+        .map(|line| line.address as i128)
+        .collect();
+    let labels: HashMap<i128, String> = lines
+        .iter()
+        .filter_map(|line| match &line.item {
+            Item::Instruction(decoded) => jump_target(decoded),
+            Item::Data(_) => None,
+        })
+        .filter(|target| starts.contains(target))
+        .map(|target| (target, format!("L{}", target)))
+        .collect();
+
+    let mut source = String::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let (text, size) = match &lines[i].item {
+            Item::Instruction(decoded) => match jump_target(decoded) {
+                Some(target) if labels.contains_key(&target) => (
+                    format!(
+                        "{} {}, #{}",
+                        decoded.itype().mnemonic(),
+                        format_operand(decoded.modes()[0], decoded.params()[0]),
+                        labels[&target]
+                    ),
+                    1,
+                ),
+                _ => (lines[i].item.to_string(), 1),
+            },
+            Item::Data(_) => {
+                let run: Vec<String> = lines[i..]
+                    .iter()
+                    .take(8)
+                    .take_while(|line| matches!(line.item, Item::Data(_)))
+                    .map(|line| line.words[0].to_string())
+                    .collect();
+                (format!(".data {}", run.join(", ")), run.len())
+            }
+        };
+        let label = match labels.get(&(lines[i].address as i128)) {
+            Some(label) => format!("{}:", label),
+            None => String::new(),
+        };
+        source.push_str(&format!("{:<7} {}\n", label, text));
+        i += size;
+    }
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing() {
+        let program = vec![1101, 12, 5, 3, 21001, 3, -2, 4, 99, 7, 5];
+        let text: Vec<String> = disassemble(&program)
+            .iter()
+            .map(|line| line.item.to_string())
+            .collect();
+        assert_eq!(
+            text,
+            vec!["add #12, #5, [3]", "add [3], #-2, rb+4", "hlt", ".data 7", ".data 5"]
+        );
+        assert!(listing(&program).starts_with("     0: 1101,12,5,3              add #12, #5, [3]\n"));
+    }
+
+    #[test]
+    fn test_to_source() {
+        let program = vec![3, 12, 1005, 12, 10, 104, 0, 1105, 1, 2, 99, -1, 0];
+        let source = to_source(&program);
+        assert_eq!(
+            source,
+            "        in [12]\n\
+             L2:     jt [12], #L10\n\
+             \x20       out #0\n\
+             \x20       jt #1, #L2\n\
+             L10:    hlt\n\
+             \x20       .data -1, 0\n"
+        );
+        assert_eq!(crate::asm::assemble(&source), Ok(program));
+    }
+
+    #[test]
+    fn test_undecodable_words() {
+        // bad opcode, bad mode, extra opcode digits and an instruction running past the end
+        let program = vec![42, 301, 100099, 1101, 1, 2];
+        let items: Vec<Item> = disassemble(&program).into_iter().map(|l| l.item).collect();
+        assert_eq!(
+            items,
+            vec![42, 301, 100099, 1101, 1, 2]
+                .into_iter()
+                .map(Item::Data)
+                .collect::<Vec<Item>>()
+        );
+    }
+}
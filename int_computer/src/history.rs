@@ -0,0 +1,167 @@
+use crate::computer::Instruction;
+use std::collections::VecDeque;
+
+/// What one executed instruction changed, enough to put the machine back as it was before.
+#[derive(Debug, Clone)]
+pub(crate) struct Entry<W> {
+    pub(crate) instruction_pointer: i128,
+    pub(crate) relative_base: i128,
+    pub(crate) last_instr: Option<Instruction>,
+    /// Address and previous value of every word written, in write order.
+    pub(crate) writes: Vec<(i128, W)>,
+    pub(crate) input: Option<W>,
+}
+
+/// Undo log of the last `limit` executed instructions, see `Computer::set_history`.
+#[derive(Debug, Clone)]
+pub(crate) struct History<W> {
+    limit: usize,
+    entries: VecDeque<Entry<W>>,
+    writes: Vec<(i128, W)>,
+    input: Option<W>,
+}
+
+impl<W> History<W> {
+    pub(crate) fn new(limit: usize) -> History<W> {
+        History {
+            limit,
+            entries: VecDeque::new(),
+            writes: Vec::new(),
+            input: None,
+        }
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Notes a write made by the instruction being executed.
+    pub(crate) fn write(&mut self, address: i128, old: W) {
+        self.writes.push((address, old));
+    }
+
+    /// Notes the input value consumed by the instruction being executed.
+    pub(crate) fn consume(&mut self, value: W) {
+        self.input = Some(value);
+    }
+
+    /// Closes the entry of the instruction that started in the given state, dropping the
+    /// oldest entry when the log is full.
+    pub(crate) fn commit(
+        &mut self,
+        instruction_pointer: i128,
+        relative_base: i128,
+        last_instr: Option<Instruction>,
+    ) {
+        if self.limit == 0 {
+            self.discard();
+            return;
+        }
+        if self.entries.len() == self.limit {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry {
+            instruction_pointer,
+            relative_base,
+            last_instr,
+            writes: std::mem::take(&mut self.writes),
+            input: self.input.take(),
+        });
+    }
+
+    /// Forgets what was noted for an instruction that did not complete.
+    pub(crate) fn discard(&mut self) {
+        self.writes.clear();
+        self.input = None;
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Entry<W>> {
+        self.entries.pop_back()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.discard();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::assemble;
+    use crate::computer::{Computer, State};
+
+    /// Adds up its inputs until a zero, keeping the running sum at rb+0 and printing it.
+    fn summer() -> Computer {
+        let program = assemble(
+            "
+                    arb #20
+            loop:   in [x]
+                    jf [x], #end
+                    add rb+0, [x], rb+0
+                    out rb+0
+                    jt #1, #loop
+            end:    hlt
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+        Computer::new(&program)
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut computer = summer().with_history(100);
+        let image: Vec<i128> = (0..22).map(|pos| computer.memread(pos)).collect();
+        for &v in &[3, 4, 5] {
+            computer.add_input(v);
+        }
+        assert_eq!(computer.run().unwrap(), State::WaitingInput);
+        assert_eq!(computer.get_all_output(), vec![3, 7, 12]);
+        assert_eq!(computer.history_len(), 1 + 3 * 5);
+
+        // back before the last `in`, with the value it consumed queued again
+        assert_eq!(computer.step_back(5), 5);
+        assert_eq!(computer.instruction_pointer(), 2);
+        assert_eq!(computer.memread(20), 7);
+        assert!(computer.has_input());
+
+        assert!(computer.run_back_to(7));
+        assert_eq!(computer.memread(20), 3);
+        assert_eq!(computer.step_back(100), 8);
+        assert_eq!(
+            (computer.instruction_pointer(), computer.relative_base()),
+            (0, 0)
+        );
+        let memory: Vec<i128> = (0..22).map(|pos| computer.memread(pos)).collect();
+        assert_eq!(memory, image);
+        assert!(!computer.run_back_to(0));
+
+        computer.add_input(0);
+        assert_eq!(computer.run().unwrap(), State::Done);
+        assert_eq!(computer.get_all_output(), vec![3, 7, 12]);
+    }
+
+    #[test]
+    fn test_bounded_history() {
+        let mut computer = summer();
+        computer.set_history(3);
+        for &v in &[1, 2, 0] {
+            computer.add_input(v);
+        }
+        assert_eq!(computer.run().unwrap(), State::Done);
+        assert_eq!(computer.history_len(), 3);
+        assert_eq!(computer.step_back(5), 3);
+        // the final `jf` and `in` and the `jt` closing the second iteration
+        assert_eq!(computer.instruction_pointer(), 13);
+        assert_eq!(computer.memread(20), 3);
+        assert!(computer.has_input());
+
+        computer.clear_history();
+        assert_eq!(computer.history_limit(), None);
+        assert_eq!(computer.step_back(1), 0);
+    }
+}
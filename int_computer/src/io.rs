@@ -0,0 +1,189 @@
+use crate::word::Word;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::mpsc::{Receiver, Sender};
+
+/// Where a `Computer` gets its input from once its own queue is empty.
+pub trait Input<W = i128> {
+    /// Returns the next value, or `None` if nothing is available yet, in which case the
+    /// computer stops with `State::WaitingInput` and asks again on the next `run`.
+    fn read(&mut self) -> Option<W>;
+}
+
+/// Where a `Computer` sends its output instead of its own queue.
+pub trait Output<W = i128> {
+    fn write(&mut self, value: W);
+}
+
+impl<W, F: FnMut() -> Option<W>> Input<W> for F {
+    fn read(&mut self) -> Option<W> {
+        self()
+    }
+}
+
+impl<W, F: FnMut(W)> Output<W> for F {
+    fn write(&mut self, value: W) {
+        self(value)
+    }
+}
+
+impl<W> Input<W> for VecDeque<W> {
+    fn read(&mut self) -> Option<W> {
+        self.pop_front()
+    }
+}
+
+impl<W> Output<W> for VecDeque<W> {
+    fn write(&mut self, value: W) {
+        self.push_back(value)
+    }
+}
+
+impl<W> Output<W> for Vec<W> {
+    fn write(&mut self, value: W) {
+        self.push(value)
+    }
+}
+
+/// Never blocks: an empty channel makes the computer wait for input.
+impl<W> Input<W> for Receiver<W> {
+    fn read(&mut self) -> Option<W> {
+        self.try_recv().ok()
+    }
+}
+
+/// Values sent after the receiving end is dropped are discarded.
+impl<W> Output<W> for Sender<W> {
+    fn write(&mut self, value: W) {
+        let _ = self.send(value);
+    }
+}
+
+/// Feeds the values of an iterator.
+pub struct IterInput<I>(I);
+
+impl<I: Iterator> IterInput<I> {
+    pub fn new<T: IntoIterator<IntoIter = I>>(values: T) -> IterInput<I> {
+        IterInput(values.into_iter())
+    }
+}
+
+impl<I: Iterator> Input<I::Item> for IterInput<I> {
+    fn read(&mut self) -> Option<I::Item> {
+        self.0.next()
+    }
+}
+
+/// Feeds text one character code at a time.
+pub struct AsciiInput {
+    text: VecDeque<u8>,
+}
+
+impl AsciiInput {
+    pub fn new(text: &str) -> AsciiInput {
+        AsciiInput {
+            text: text.bytes().collect(),
+        }
+    }
+
+    /// Queues `line` followed by a newline.
+    pub fn push_line(&mut self, line: &str) {
+        self.text.extend(line.bytes());
+        self.text.push_back(b'\n');
+    }
+}
+
+impl<W: Word> Input<W> for AsciiInput {
+    fn read(&mut self) -> Option<W> {
+        self.text.pop_front().map(|b| W::from(b as i32))
+    }
+}
+
+/// Writes ASCII output as text; values outside the ASCII range are written as numbers on
+/// their own line.
+pub struct AsciiOutput<W: Write> {
+    out: W,
+    error: Option<std::io::Error>,
+}
+
+impl<W: Write> AsciiOutput<W> {
+    pub fn new(out: W) -> AsciiOutput<W> {
+        AsciiOutput { out, error: None }
+    }
+
+    /// Returns the writer, or the first error met while writing to it.
+    pub fn into_inner(self) -> std::io::Result<W> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(self.out),
+        }
+    }
+}
+
+impl<W: Write, V: Word> Output<V> for AsciiOutput<W> {
+    fn write(&mut self, value: V) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = match value.to_i128().filter(|v| (0..128).contains(v)) {
+            Some(code) => self.out.write_all(&[code as u8]),
+            None => writeln!(self.out, "{}", value),
+        };
+        self.error = result.err();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::computer::{Computer, State};
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_sources_and_sinks() {
+        // adds pairs of inputs until it reads a zero
+        let program = assemble(
+            "
+            loop:   in [a]
+                    jf [a], #end
+                    in [b]
+                    add [a], [b], [a]
+                    out [a]
+                    jt #1, #loop
+            end:    hlt
+            a:      .data 0
+            b:      .data 0
+            ",
+        )
+        .unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let mut computer = Computer::new(&program)
+            .with_input_source(IterInput::new(vec![1, 2, 3, 4]))
+            .with_output_sink(tx);
+        assert_eq!(computer.run().unwrap(), State::WaitingInput);
+        assert_eq!(rx.try_iter().collect::<Vec<i128>>(), vec![3, 7]);
+        assert_eq!(computer.get_output(), None);
+
+        let mut rest = vec![0, 5, 10];
+        computer.set_input_source(move || rest.pop());
+        computer.take_output_sink();
+        assert_eq!(computer.run().unwrap(), State::Done);
+        assert_eq!(computer.get_all_output(), vec![15]);
+    }
+
+    #[test]
+    fn test_ascii() {
+        let mut input = AsciiInput::new("Hi");
+        input.push_line("!");
+        let codes: Vec<i128> = std::iter::from_fn(|| input.read()).collect();
+        assert_eq!(codes, vec![72, 105, 33, 10]);
+
+        let mut output = AsciiOutput::new(Vec::new());
+        for &value in &[72i128, 105, 10, 1000] {
+            output.write(value);
+        }
+        assert_eq!(output.into_inner().unwrap(), b"Hi\n1000\n");
+    }
+}
@@ -0,0 +1,212 @@
+use crate::word::Word;
+use std::collections::HashMap;
+
+/// Backing store for a `Computer`. Unwritten addresses read as 0.
+pub trait Memory {
+    type Word: Word;
+
+    fn from_program(program: &[Self::Word]) -> Self
+    where
+        Self: Sized;
+
+    fn read(&self, address: i128) -> Self::Word;
+
+    fn write(&mut self, address: i128, value: Self::Word);
+
+    /// One past the highest non-negative address ever loaded or written.
+    fn end(&self) -> i128;
+
+    /// Every stored word as `(address, value)`, in address order.
+    fn words(&self) -> Vec<(i128, Self::Word)>;
+}
+
+/// Every word lives in a `HashMap`; cheap for scattered addresses but every access is hashed.
+#[derive(Clone, Debug, Default)]
+pub struct SparseMemory<W: Word = i128> {
+    words: HashMap<i128, W>,
+    end: i128,
+}
+
+impl<W: Word> Memory for SparseMemory<W> {
+    type Word = W;
+
+    fn from_program(program: &[W]) -> SparseMemory<W> {
+        SparseMemory {
+            words: (0..).zip(program.iter().cloned()).collect(),
+            end: program.len() as i128,
+        }
+    }
+
+    fn read(&self, address: i128) -> W {
+        self.words.get(&address).cloned().unwrap_or_default()
+    }
+
+    fn write(&mut self, address: i128, value: W) {
+        self.words.insert(address, value);
+        self.end = self.end.max(address.saturating_add(1));
+    }
+
+    fn end(&self) -> i128 {
+        self.end
+    }
+
+    fn words(&self) -> Vec<(i128, W)> {
+        let mut words: Vec<(i128, W)> = self.words.iter().map(|(a, v)| (*a, v.clone())).collect();
+        words.sort_by_key(|w| w.0);
+        words
+    }
+}
+
+/// How far past the end of the vector a write may land and still grow it.
+/// Anything further away goes to the sparse overflow map instead.
+const MAX_GAP: i128 = 1 << 16;
+
+/// Contiguous words that grow on writes past the end, with a `HashMap` fallback for
+/// negative or far away addresses so a single huge address does not allocate gigabytes.
+#[derive(Clone, Debug, Default)]
+pub struct FlatMemory<W: Word = i128> {
+    words: Vec<W>,
+    sparse: HashMap<i128, W>,
+    end: i128,
+}
+
+impl<W: Word> FlatMemory<W> {
+    fn grow(&mut self, len: usize) {
+        let old = self.words.len();
+        self.words.resize(len, W::default());
+        if self.sparse.is_empty() {
+            return;
+        }
+        // look up whichever is smaller, the new addresses or the overflow map
+        let moved: Vec<i128> = if len - old < self.sparse.len() {
+            (old as i128..len as i128)
+                .filter(|a| self.sparse.contains_key(a))
+                .collect()
+        } else {
+            self.sparse
+                .keys()
+                .filter(|&&a| a >= old as i128 && a < len as i128)
+                .cloned()
+                .collect()
+        };
+        for address in moved {
+            self.words[address as usize] = self.sparse.remove(&address).unwrap_or_default();
+        }
+    }
+}
+
+impl<W: Word> Memory for FlatMemory<W> {
+    type Word = W;
+
+    fn from_program(program: &[W]) -> FlatMemory<W> {
+        FlatMemory {
+            words: program.to_vec(),
+            sparse: HashMap::new(),
+            end: program.len() as i128,
+        }
+    }
+
+    fn read(&self, address: i128) -> W {
+        if address >= 0 && address < self.words.len() as i128 {
+            self.words[address as usize].clone()
+        } else {
+            self.sparse.get(&address).cloned().unwrap_or_default()
+        }
+    }
+
+    fn write(&mut self, address: i128, value: W) {
+        let len = self.words.len() as i128;
+        if address >= 0 && address < len {
+            self.words[address as usize] = value;
+        } else if address >= len && address < len + MAX_GAP {
+            self.grow(address as usize + 1);
+            self.words[address as usize] = value;
+        } else {
+            self.sparse.insert(address, value);
+        }
+        self.end = self.end.max(address.saturating_add(1));
+    }
+
+    fn end(&self) -> i128 {
+        self.end
+    }
+
+    fn words(&self) -> Vec<(i128, W)> {
+        let flat = (self.words.len() as i128).min(self.end.max(0)) as usize;
+        let mut words: Vec<(i128, W)> = (0..)
+            .zip(self.words[..flat].iter().cloned())
+            .chain(self.sparse.iter().map(|(a, v)| (*a, v.clone())))
+            .collect();
+        words.sort_by_key(|w| w.0);
+        words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::Computer;
+
+    fn check_backend<M: Memory<Word = i128>>() {
+        let mut memory = M::from_program(&[1, 2, 3]);
+        assert_eq!(memory.end(), 3);
+        assert_eq!((memory.read(0), memory.read(2), memory.read(3)), (1, 3, 0));
+
+        memory.write(1, 20);
+        memory.write(10, 7);
+        assert_eq!((memory.read(1), memory.read(10), memory.read(9)), (20, 7, 0));
+        assert_eq!(memory.end(), 11);
+
+        memory.write(1 << 100, 5);
+        memory.write(-4, 6);
+        assert_eq!((memory.read(1 << 100), memory.read(-4)), (5, 6));
+        assert_eq!(memory.end(), (1 << 100) + 1);
+        let words = memory.words();
+        assert_eq!(words.first(), Some(&(-4, 6)));
+        assert_eq!(words.last(), Some(&(1 << 100, 5)));
+        assert_eq!(words.iter().find(|w| w.0 == 10), Some(&(10, 7)));
+
+        // quine from day 9, reads and writes past the end of the program
+        let quine = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let mut computer = Computer::with_memory(M::from_program(&quine));
+        computer.run().unwrap();
+        assert_eq!(computer.get_all_output(), quine);
+    }
+
+    #[test]
+    fn test_sparse_memory() {
+        check_backend::<SparseMemory>();
+    }
+
+    #[test]
+    fn test_flat_memory() {
+        check_backend::<FlatMemory>();
+    }
+
+    #[test]
+    fn test_flat_memory_absorbs_sparse_words() {
+        let mut memory = FlatMemory::<i128>::from_program(&[1]);
+        memory.write(MAX_GAP + 10, 42);
+        assert_eq!(memory.words.len(), 1);
+        for address in 1..MAX_GAP + 20 {
+            memory.write(address, memory.read(address));
+        }
+        assert!(memory.sparse.is_empty());
+        assert_eq!(memory.read(MAX_GAP + 10), 42);
+    }
+
+    #[test]
+    fn test_exact_growth() {
+        let mut memory = FlatMemory::<i128>::from_program(&[1, 2, 3]);
+        memory.write(10, 5);
+        assert_eq!(memory.words.len(), 11);
+
+        // writing upward a word at a time holds just the words written
+        for address in 11..1000 {
+            memory.write(address, 5);
+        }
+        assert_eq!(memory.words.len(), 1000);
+    }
+}
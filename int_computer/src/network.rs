@@ -0,0 +1,490 @@
+use crate::computer::{Computer, State, VmError};
+use crate::io::{Input, Output};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often `Network::next_event` looks for an idle network while no packet is moving.
+const POLL: Duration = Duration::from_millis(1);
+
+/// Values sent by a machine: a destination address followed by the packet values.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Packet {
+    pub to: i128,
+    pub values: Vec<i128>,
+}
+
+#[derive(Debug)]
+pub enum Event {
+    /// A packet addressed to something outside the network.
+    Packet(Packet),
+    /// Every machine keeps asking for input and no packet is in flight. Reported again on
+    /// every poll until something is sent.
+    Idle,
+    /// A machine stopped with `hlt` or a fault.
+    Halted {
+        machine: usize,
+        result: Result<(), VmError>,
+    },
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct NetworkConfig {
+    packet_size: usize,
+    empty_input: Option<i128>,
+    idle_reads: u32,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> NetworkConfig {
+        NetworkConfig {
+            packet_size: 2,
+            empty_input: Some(-1),
+            idle_reads: 2,
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// Two values per packet, `-1` read when nothing was received, as in day 23.
+    pub fn new() -> NetworkConfig {
+        NetworkConfig::default()
+    }
+
+    /// Number of values following the destination address in a packet.
+    pub fn packet_size(mut self, n: usize) -> NetworkConfig {
+        self.packet_size = n;
+        self
+    }
+
+    /// Value read by a machine with no pending packet.
+    pub fn empty_input(mut self, value: i128) -> NetworkConfig {
+        self.empty_input = Some(value);
+        self
+    }
+
+    /// Makes reads block until a packet arrives instead of returning the empty input value.
+    pub fn blocking(mut self) -> NetworkConfig {
+        self.empty_input = None;
+        self
+    }
+
+    /// Consecutive empty reads after which a machine counts as idle.
+    pub fn idle_reads(mut self, n: u32) -> NetworkConfig {
+        self.idle_reads = n;
+        self
+    }
+}
+
+enum Message {
+    Packet(Packet),
+    Stopped(usize, Result<State, VmError>),
+}
+
+/// Counters shared by the machines and the router to detect an idle network.
+struct Shared {
+    stop: AtomicBool,
+    /// Incremented on every value read from a packet or written by a machine.
+    activity: AtomicU64,
+    /// Values written by machines that the router has not passed on yet.
+    in_flight: AtomicUsize,
+    /// Values sent to each machine that it has not read yet; they no longer count once the
+    /// machine stopped.
+    queued: Vec<AtomicUsize>,
+    /// Consecutive empty reads of each machine, up to `u32::MAX - 1`; `u32::MAX` once it
+    /// stopped.
+    starved: Vec<AtomicU32>,
+}
+
+struct Inbox {
+    machine: usize,
+    receiver: Receiver<i128>,
+    empty_input: Option<i128>,
+    shared: Arc<Shared>,
+}
+
+impl Inbox {
+    fn received(&self, value: i128) -> Option<i128> {
+        self.shared.starved[self.machine].store(0, Ordering::SeqCst);
+        self.shared.activity.fetch_add(1, Ordering::SeqCst);
+        self.shared.queued[self.machine].fetch_sub(1, Ordering::SeqCst);
+        Some(value)
+    }
+}
+
+impl Input for Inbox {
+    fn read(&mut self) -> Option<i128> {
+        if self.shared.stop.load(Ordering::SeqCst) {
+            return None;
+        }
+        match self.receiver.try_recv() {
+            Ok(value) => self.received(value),
+            Err(TryRecvError::Disconnected) => None,
+            Err(TryRecvError::Empty) => {
+                // stays below `u32::MAX`, which marks a stopped machine
+                let _ = self.shared.starved[self.machine].fetch_update(
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                    |s| Some(s.saturating_add(1).min(u32::MAX - 1)),
+                );
+                match self.empty_input {
+                    Some(value) => {
+                        thread::yield_now();
+                        Some(value)
+                    }
+                    None => match self.receiver.recv() {
+                        Ok(value) => self.received(value),
+                        Err(_) => None,
+                    },
+                }
+            }
+        }
+    }
+}
+
+struct Outbox {
+    pending: Vec<i128>,
+    packet_size: usize,
+    sender: Sender<Message>,
+    shared: Arc<Shared>,
+}
+
+impl Output for Outbox {
+    fn write(&mut self, value: i128) {
+        self.shared.activity.fetch_add(1, Ordering::SeqCst);
+        self.shared.in_flight.fetch_add(1, Ordering::SeqCst);
+        self.pending.push(value);
+        if self.pending.len() > self.packet_size {
+            let values = self.pending.split_off(1);
+            let packet = Packet {
+                to: self.pending.pop().unwrap_or(0),
+                values,
+            };
+            let _ = self.sender.send(Message::Packet(packet));
+        }
+    }
+}
+
+/// Runs one `Computer` per thread, each reading its own address first and then exchanging
+/// packets with the others.
+///
+/// Machines are addressed `0..n`; packets sent elsewhere are handed to the caller through
+/// `next_event`, which is also where packets travel between machines, so the network only
+/// moves while it is being polled.
+pub struct Network {
+    config: NetworkConfig,
+    inboxes: Vec<Sender<i128>>,
+    events: Receiver<Message>,
+    shared: Arc<Shared>,
+    handles: Vec<JoinHandle<Computer>>,
+    running: usize,
+}
+
+impl Network {
+    pub fn new(program: &[i128], machines: usize, config: NetworkConfig) -> Network {
+        let shared = Arc::new(Shared {
+            stop: AtomicBool::new(false),
+            activity: AtomicU64::new(0),
+            in_flight: AtomicUsize::new(0),
+            queued: (0..machines).map(|_| AtomicUsize::new(0)).collect(),
+            starved: (0..machines).map(|_| AtomicU32::new(0)).collect(),
+        });
+        let (events_sender, events) = mpsc::channel();
+        let mut inboxes = Vec::new();
+        let mut handles = Vec::new();
+        for machine in 0..machines {
+            let (sender, receiver) = mpsc::channel();
+            let mut computer = Computer::new(program)
+                .with_input_source(Inbox {
+                    machine,
+                    receiver,
+                    empty_input: config.empty_input,
+                    shared: shared.clone(),
+                })
+                .with_output_sink(Outbox {
+                    pending: Vec::new(),
+                    packet_size: config.packet_size,
+                    sender: events_sender.clone(),
+                    shared: shared.clone(),
+                });
+            computer.add_input_128(machine as i128);
+            let events = events_sender.clone();
+            let thread_shared = shared.clone();
+            handles.push(thread::spawn(move || {
+                let result = computer.run();
+                thread_shared.starved[machine].store(u32::MAX, Ordering::SeqCst);
+                let _ = events.send(Message::Stopped(machine, result));
+                computer
+            }));
+            inboxes.push(sender);
+        }
+
+        Network {
+            config,
+            inboxes,
+            events,
+            shared,
+            handles,
+            running: machines,
+        }
+    }
+
+    /// Delivers a packet to a machine; packets to unknown addresses are dropped.
+    pub fn send(&self, packet: Packet) {
+        if let Some(inbox) = self.inbox(packet.to) {
+            self.shared.queued[packet.to as usize].fetch_add(packet.values.len(), Ordering::SeqCst);
+            for value in packet.values {
+                let _ = inbox.send(value);
+            }
+        }
+    }
+
+    fn inbox(&self, address: i128) -> Option<&Sender<i128>> {
+        if address >= 0 && address < self.inboxes.len() as i128 {
+            Some(&self.inboxes[address as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Routes packets between machines until something needs the caller's attention.
+    /// Returns `None` once every machine has stopped.
+    pub fn next_event(&mut self) -> Option<Event> {
+        while self.running > 0 {
+            match self.events.recv_timeout(POLL) {
+                Ok(Message::Packet(packet)) => {
+                    let written = 1 + packet.values.len();
+                    if self.inbox(packet.to).is_some() {
+                        self.send(packet);
+                        self.shared.in_flight.fetch_sub(written, Ordering::SeqCst);
+                    } else {
+                        self.shared.in_flight.fetch_sub(written, Ordering::SeqCst);
+                        return Some(Event::Packet(packet));
+                    }
+                }
+                Ok(Message::Stopped(machine, result)) => {
+                    self.running -= 1;
+                    return Some(Event::Halted {
+                        machine,
+                        result: result.map(|_| ()),
+                    });
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.is_idle() {
+                        return Some(Event::Idle);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        None
+    }
+
+    fn starved(&self) -> Vec<u32> {
+        self.shared
+            .starved
+            .iter()
+            .map(|s| s.load(Ordering::SeqCst))
+            .collect()
+    }
+
+    fn quiet(&self, starved: &[u32]) -> bool {
+        // a blocked read counts once and cannot come back, so one is enough when blocking
+        let reads = match self.config.empty_input {
+            Some(_) => self.config.idle_reads.max(1),
+            None => 1,
+        };
+        let waiting = |(&s, queued): (&u32, &AtomicUsize)| {
+            s == u32::MAX || (s >= reads && queued.load(Ordering::SeqCst) == 0)
+        };
+        self.shared.in_flight.load(Ordering::SeqCst) == 0
+            && starved.iter().zip(&self.shared.queued).all(waiting)
+    }
+
+    /// The network is idle when nothing is in flight, every machine has been starved for a
+    /// while and, when reads do not block, every machine came back for input once more
+    /// without any value being read or written in the meantime.
+    fn is_idle(&self) -> bool {
+        let activity = self.shared.activity.load(Ordering::SeqCst);
+        let before = self.starved();
+        if !self.quiet(&before) {
+            return false;
+        }
+        if self.config.empty_input.is_some() {
+            let deadline = Instant::now() + POLL * 10;
+            loop {
+                let now = self.starved();
+                if now.iter().zip(&before).any(|(n, b)| n < b) || Instant::now() > deadline {
+                    return false;
+                }
+                if now.iter().zip(&before).all(|(n, b)| n > b || *b == u32::MAX) {
+                    break;
+                }
+                thread::yield_now();
+            }
+        }
+        self.shared.activity.load(Ordering::SeqCst) == activity && self.quiet(&self.starved())
+    }
+
+    fn stop(&mut self) {
+        self.shared.stop.store(true, Ordering::SeqCst);
+        // wakes up machines blocked on a read
+        self.inboxes.clear();
+    }
+
+    /// Stops every machine at its next read and returns them in address order.
+    /// A machine that never reads again is waited for forever.
+    pub fn shutdown(mut self) -> Vec<Computer> {
+        self.stop();
+        std::mem::take(&mut self.handles)
+            .into_iter()
+            .map(|handle| handle.join().expect("machine thread panicked"))
+            .collect()
+    }
+}
+
+impl Drop for Network {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    /// Announces itself to address 99, then forwards every packet it receives there.
+    fn relay() -> Vec<i128> {
+        assemble(
+            "
+                    in [addr]
+                    mul [addr], #10, [y]
+                    out #99
+                    out [addr]
+                    out [y]
+            loop:   in [x]
+                    eq [x], #-1, [flag]
+                    jt [flag], #loop
+                    in [y]
+                    out #99
+                    out [x]
+                    out [y]
+                    jt #1, #loop
+            addr:   .data 0
+            x:      .data 0
+            y:      .data 0
+            flag:   .data 0
+            ",
+        )
+        .unwrap()
+    }
+
+    fn check_network(config: NetworkConfig) {
+        let mut network = Network::new(&relay(), 3, config);
+        let mut announced = Vec::new();
+        for _ in 0..3 {
+            match network.next_event() {
+                Some(Event::Packet(packet)) => announced.push(packet.values),
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+        announced.sort();
+        assert_eq!(announced, vec![vec![0, 0], vec![1, 10], vec![2, 20]]);
+        assert!(matches!(network.next_event(), Some(Event::Idle)));
+
+        network.send(Packet {
+            to: 2,
+            values: vec![7, 8],
+        });
+        let forwarded = Packet {
+            to: 99,
+            values: vec![7, 8],
+        };
+        assert!(matches!(network.next_event(), Some(Event::Packet(p)) if p == forwarded));
+        assert!(matches!(network.next_event(), Some(Event::Idle)));
+        assert_eq!(network.shutdown().len(), 3);
+    }
+
+    #[test]
+    fn test_network_polling() {
+        check_network(NetworkConfig::new());
+    }
+
+    #[test]
+    fn test_network_blocking() {
+        check_network(NetworkConfig::new().blocking());
+    }
+
+    #[test]
+    fn test_network_halts() {
+        let mut network = Network::new(&[3, 0, 99], 2, NetworkConfig::new());
+        let mut halted = Vec::new();
+        while let Some(event) = network.next_event() {
+            match event {
+                Event::Halted { machine, result } => {
+                    assert!(result.is_ok());
+                    halted.push(machine);
+                }
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+        halted.sort();
+        assert_eq!(halted, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_starved_count_saturates() {
+        let shared = Arc::new(Shared {
+            stop: AtomicBool::new(false),
+            activity: AtomicU64::new(0),
+            in_flight: AtomicUsize::new(0),
+            queued: vec![AtomicUsize::new(0)],
+            starved: vec![AtomicU32::new(u32::MAX - 3)],
+        });
+        let (_sender, receiver) = mpsc::channel();
+        let mut inbox = Inbox {
+            machine: 0,
+            receiver,
+            empty_input: Some(-1),
+            shared: shared.clone(),
+        };
+        for _ in 0..4 {
+            assert_eq!(inbox.read(), Some(-1));
+        }
+        assert_eq!(shared.starved[0].load(Ordering::SeqCst), u32::MAX - 1);
+    }
+
+    #[test]
+    fn test_packets_to_halted_machine() {
+        // machine 0 halts at once, machine 1 sends it a packet and then polls for ever
+        let program = assemble(
+            "
+                    in [addr]
+                    jf [addr], #end
+                    out #0
+                    out #7
+                    out #8
+            loop:   in [x]
+                    jt #1, #loop
+            end:    hlt
+            addr:   .data 0
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut network = Network::new(&program, 2, NetworkConfig::new());
+        assert!(matches!(
+            network.next_event(),
+            Some(Event::Halted { machine: 0, .. })
+        ));
+        assert!(matches!(network.next_event(), Some(Event::Idle)));
+        network.send(Packet {
+            to: 0,
+            values: vec![1, 2],
+        });
+        assert!(matches!(network.next_event(), Some(Event::Idle)));
+    }
+}
@@ -0,0 +1,425 @@
+use crate::computer::{Computer, Instruction, InstructionType, MemoryWrite, VmError, MAX_PARAMS};
+use crate::memory::Memory;
+use crate::word::{Overflow, Word};
+
+/// What the dispatcher does after an opcode handler returns.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Flow {
+    /// Go on with the next instruction.
+    Continue,
+    /// Stop with `State::WaitingInput` and run the same instruction again on the next call.
+    WaitInput,
+    /// Stop with `State::Done`, staying on the instruction.
+    Halt,
+}
+
+pub type Handler<M> = fn(&mut Exec<M>) -> Result<Flow, VmError>;
+
+/// An opcode with its arity, the parameters it writes to and its semantics.
+pub struct Opcode<M: Memory> {
+    itype: InstructionType,
+    writes: &'static [usize],
+    handler: Handler<M>,
+}
+
+impl<M: Memory> Clone for Opcode<M> {
+    fn clone(&self) -> Opcode<M> {
+        *self
+    }
+}
+
+impl<M: Memory> Copy for Opcode<M> {}
+
+impl<M: Memory> Opcode<M> {
+    /// Describes an extra opcode; `code` is the value of the two low digits of the opcode.
+    ///
+    /// # Panics
+    ///
+    /// If `code` is not in `1..100`, `arity` is above `MAX_PARAMS` or a write position is not
+    /// below `arity`.
+    pub fn new(
+        code: u8,
+        mnemonic: &'static str,
+        arity: usize,
+        writes: &'static [usize],
+        handler: Handler<M>,
+    ) -> Opcode<M> {
+        let itype = InstructionType::Custom {
+            code,
+            arity,
+            mnemonic,
+        };
+        Opcode::with_type(itype, writes, handler)
+    }
+
+    fn with_type(
+        itype: InstructionType,
+        writes: &'static [usize],
+        handler: Handler<M>,
+    ) -> Opcode<M> {
+        assert!(
+            (1..100).contains(&itype.code()),
+            "opcode {} is out of range",
+            itype.code()
+        );
+        assert!(
+            itype.arity() <= MAX_PARAMS,
+            "opcode {} has too many parameters",
+            itype.code()
+        );
+        assert!(
+            writes.iter().all(|&w| w < itype.arity()),
+            "opcode {} writes to a parameter it does not have",
+            itype.code()
+        );
+        Opcode {
+            itype,
+            writes,
+            handler,
+        }
+    }
+
+    pub fn itype(&self) -> InstructionType {
+        self.itype
+    }
+
+    /// Parameters that receive a result and are therefore addresses rather than values.
+    pub fn writes(&self) -> &'static [usize] {
+        self.writes
+    }
+
+    pub fn handler(&self) -> Handler<M> {
+        self.handler
+    }
+}
+
+/// Opcodes known to a `Computer`, indexed by their two low digits.
+pub struct Opcodes<M: Memory> {
+    table: Vec<Option<Opcode<M>>>,
+}
+
+impl<M: Memory> Clone for Opcodes<M> {
+    fn clone(&self) -> Opcodes<M> {
+        Opcodes {
+            table: self.table.clone(),
+        }
+    }
+}
+
+impl<M: Memory> Opcodes<M> {
+    pub fn empty() -> Opcodes<M> {
+        Opcodes {
+            table: vec![None; 100],
+        }
+    }
+
+    /// The ten opcodes of the Intcode specification.
+    pub fn builtin() -> Opcodes<M> {
+        use InstructionType::*;
+        let mut opcodes = Opcodes::empty();
+        let builtins: [(InstructionType, &'static [usize], Handler<M>); 10] = [
+            (Add, &[2], add),
+            (Multiply, &[2], multiply),
+            (Input, &[0], input),
+            (Output, &[], output),
+            (JumpIfTrue, &[], jump_if_true),
+            (JumpIfFalse, &[], jump_if_false),
+            (LessThan, &[2], less_than),
+            (Equals, &[2], equals),
+            (AdjustBase, &[], adjust_base),
+            (Exit, &[], exit),
+        ];
+        for (itype, writes, handler) in builtins.iter() {
+            opcodes.insert(Opcode::with_type(*itype, writes, *handler));
+        }
+        opcodes
+    }
+
+    /// Adds `opcode`, returning the one it replaces.
+    pub fn insert(&mut self, opcode: Opcode<M>) -> Option<Opcode<M>> {
+        self.table[opcode.itype.code() as usize].replace(opcode)
+    }
+
+    pub fn remove(&mut self, code: u8) -> Option<Opcode<M>> {
+        self.table.get_mut(code as usize).and_then(|o| o.take())
+    }
+
+    /// Looks up a full opcode, parameter modes included.
+    pub fn get(&self, opcode: i128) -> Option<&Opcode<M>> {
+        let code = opcode % 100;
+        if code < 0 {
+            return None;
+        }
+        self.table[code as usize].as_ref()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Opcode<M>> {
+        self.table.iter().flatten()
+    }
+}
+
+/// What an opcode handler can see and do while its instruction executes.
+pub struct Exec<'a, M: Memory> {
+    pub(crate) computer: &'a mut Computer<M>,
+    pub(crate) instruction: Instruction,
+    pub(crate) writes: &'static [usize],
+    pub(crate) written: Vec<MemoryWrite<M::Word>>,
+    pub(crate) taken: Option<M::Word>,
+}
+
+impl<'a, M: Memory> Exec<'a, M> {
+    pub fn instruction(&self) -> &Instruction {
+        &self.instruction
+    }
+
+    /// Value of parameter `param`.
+    pub fn read(&self, param: usize) -> Result<M::Word, VmError> {
+        self.computer
+            .load(&self.instruction, self.instruction.operands[param])
+    }
+
+    /// Value of parameter `param` used as an address, jump target or base offset.
+    pub fn read_address(&self, param: usize) -> Result<i128, VmError> {
+        self.read(param)?
+            .to_i128()
+            .ok_or_else(|| self.out_of_range())
+    }
+
+    fn out_of_range(&self) -> VmError {
+        VmError::AddressOutOfRange {
+            ip: self.instruction.address,
+            opcode: self.instruction.opcode,
+        }
+    }
+
+    /// Stores `value` at the address given by parameter `param`.
+    ///
+    /// # Panics
+    ///
+    /// If `param` was not declared as a write position of the opcode.
+    pub fn write(&mut self, param: usize, value: M::Word) -> Result<(), VmError> {
+        assert!(
+            self.writes.contains(&param),
+            "parameter {} of opcode {} is not a write position",
+            param,
+            self.instruction.opcode
+        );
+        let address = self.instruction.operands[param];
+        let write = self.computer.store(&self.instruction, address, value)?;
+        self.written.push(write);
+        Ok(())
+    }
+
+    pub fn jump(&mut self, target: i128) {
+        self.computer.instruction_pointer = target;
+    }
+
+    pub fn relative_base(&self) -> i128 {
+        self.computer.relative_base
+    }
+
+    pub fn adjust_relative_base(&mut self, delta: i128) -> Result<(), VmError> {
+        self.computer.relative_base = self
+            .computer
+            .relative_base
+            .checked_add(delta)
+            .ok_or_else(|| self.out_of_range())?;
+        Ok(())
+    }
+
+    /// Takes the next input value. It is given back if the handler fails or waits.
+    pub fn input(&mut self) -> Option<M::Word> {
+        let value = self.computer.take_input();
+        if value.is_some() {
+            self.taken = value.clone();
+        }
+        value
+    }
+
+    pub fn output(&mut self, value: M::Word) {
+        self.computer.put_output(value);
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.computer.overflow()
+    }
+
+    /// `a + b` under the overflow policy of the computer.
+    pub fn add(&self, a: &M::Word, b: &M::Word) -> Result<M::Word, VmError> {
+        self.overflow()
+            .add(a, b)
+            .ok_or_else(|| self.overflow_error())
+    }
+
+    /// `a * b` under the overflow policy of the computer.
+    pub fn mul(&self, a: &M::Word, b: &M::Word) -> Result<M::Word, VmError> {
+        self.overflow()
+            .mul(a, b)
+            .ok_or_else(|| self.overflow_error())
+    }
+
+    fn overflow_error(&self) -> VmError {
+        VmError::Overflow {
+            ip: self.instruction.address,
+            opcode: self.instruction.opcode,
+        }
+    }
+}
+
+fn add<M: Memory>(exec: &mut Exec<M>) -> Result<Flow, VmError> {
+    let value = exec.add(&exec.read(0)?, &exec.read(1)?)?;
+    exec.write(2, value)?;
+    Ok(Flow::Continue)
+}
+
+fn multiply<M: Memory>(exec: &mut Exec<M>) -> Result<Flow, VmError> {
+    let value = exec.mul(&exec.read(0)?, &exec.read(1)?)?;
+    exec.write(2, value)?;
+    Ok(Flow::Continue)
+}
+
+fn input<M: Memory>(exec: &mut Exec<M>) -> Result<Flow, VmError> {
+    match exec.input() {
+        Some(value) => {
+            exec.write(0, value)?;
+            Ok(Flow::Continue)
+        }
+        None => Ok(Flow::WaitInput),
+    }
+}
+
+fn output<M: Memory>(exec: &mut Exec<M>) -> Result<Flow, VmError> {
+    let value = exec.read(0)?;
+    exec.output(value);
+    Ok(Flow::Continue)
+}
+
+fn jump_if_true<M: Memory>(exec: &mut Exec<M>) -> Result<Flow, VmError> {
+    if !exec.read(0)?.is_zero() {
+        let target = exec.read_address(1)?;
+        exec.jump(target);
+    }
+    Ok(Flow::Continue)
+}
+
+fn jump_if_false<M: Memory>(exec: &mut Exec<M>) -> Result<Flow, VmError> {
+    if exec.read(0)?.is_zero() {
+        let target = exec.read_address(1)?;
+        exec.jump(target);
+    }
+    Ok(Flow::Continue)
+}
+
+fn less_than<M: Memory>(exec: &mut Exec<M>) -> Result<Flow, VmError> {
+    let value = exec.read(0)? < exec.read(1)?;
+    exec.write(2, M::Word::from(value as i32))?;
+    Ok(Flow::Continue)
+}
+
+fn equals<M: Memory>(exec: &mut Exec<M>) -> Result<Flow, VmError> {
+    let value = exec.read(0)? == exec.read(1)?;
+    exec.write(2, M::Word::from(value as i32))?;
+    Ok(Flow::Continue)
+}
+
+fn adjust_base<M: Memory>(exec: &mut Exec<M>) -> Result<Flow, VmError> {
+    let delta = exec.read_address(0)?;
+    exec.adjust_relative_base(delta)?;
+    Ok(Flow::Continue)
+}
+
+fn exit<M: Memory>(_: &mut Exec<M>) -> Result<Flow, VmError> {
+    Ok(Flow::Halt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::{State, Step};
+    use crate::disasm::Item;
+    use crate::memory::FlatMemory;
+    use crate::protect::{MemoryFault, MemoryPolicy};
+
+    /// `swp a, b`: exchanges two memory words.
+    fn swap<M: Memory>(exec: &mut Exec<M>) -> Result<Flow, VmError> {
+        let (a, b) = (exec.read(0)?, exec.read(1)?);
+        exec.write(0, b)?;
+        exec.write(1, a)?;
+        Ok(Flow::Continue)
+    }
+
+    #[test]
+    fn test_custom_opcode() {
+        // swp [7], [8]; out [7]; hlt
+        let program = [20, 7, 8, 4, 7, 99, 0, 1, 2];
+        let mut computer = Computer::new(&program);
+        assert!(computer.run().is_err());
+
+        let mut computer = Computer::new(&program);
+        assert!(computer
+            .register_opcode(Opcode::new(20, "swp", 2, &[0, 1], swap))
+            .is_none());
+        assert_eq!(computer.run().unwrap(), State::Done);
+        assert_eq!(computer.get_all_output(), vec![2]);
+        assert_eq!(computer.memread(8), 1);
+
+        let decoded = computer.decode_at(0).unwrap();
+        assert_eq!(Item::Instruction(decoded).to_string(), "swp [7], [8]");
+    }
+
+    #[test]
+    fn test_multiple_writes() {
+        let program = [20, 7, 8, 99, 0, 0, 0, 1, 2];
+        let mut computer = Computer::new(&program);
+        computer.register_opcode(Opcode::new(20, "swp", 2, &[0, 1], swap));
+        let writes = vec![
+            MemoryWrite {
+                address: 7,
+                old: 1,
+                new: 2,
+            },
+            MemoryWrite {
+                address: 8,
+                old: 2,
+                new: 1,
+            },
+        ];
+        assert_eq!(
+            computer.step().unwrap(),
+            Step::Executed { address: 0, writes }
+        );
+
+        // the second write faults, so the first one is taken back
+        let mut computer = Computer::new(&program)
+            .with_memory_policy(MemoryPolicy::new().read_only(8..9))
+            .with_history(10);
+        computer.register_opcode(Opcode::new(20, "swp", 2, &[0, 1], swap));
+        match computer.step() {
+            Err(VmError::Fault { fault, .. }) => {
+                assert_eq!(fault, MemoryFault::ReadOnly { address: 8 })
+            }
+            other => panic!("expected a memory fault, got {:?}", other),
+        }
+        assert_eq!((computer.memread(7), computer.memread(8)), (1, 2));
+        assert_eq!(computer.instruction_pointer(), 0);
+        assert_eq!(computer.history_len(), 0);
+    }
+
+    #[test]
+    fn test_replace_builtin() {
+        // `in` that reads every value twice as large
+        fn double_input(exec: &mut Exec<FlatMemory>) -> Result<Flow, VmError> {
+            match exec.input() {
+                Some(value) => exec.write(0, 2 * value).map(|_| Flow::Continue),
+                None => Ok(Flow::WaitInput),
+            }
+        }
+        let mut computer = Computer::new(&[3, 5, 4, 5, 99, 0]);
+        let replaced = computer.register_opcode(Opcode::new(3, "in2", 1, &[0], double_input));
+        assert_eq!(replaced.map(|o| o.itype()), Some(InstructionType::Input));
+        assert_eq!(computer.run().unwrap(), State::WaitingInput);
+        computer.add_input(21);
+        assert_eq!(computer.run().unwrap(), State::Done);
+        assert_eq!(computer.get_output(), Some(42));
+    }
+}
@@ -0,0 +1,264 @@
+use crate::computer::{Computer, State, VmError};
+use crate::memory::{FlatMemory, Memory};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Topology {
+    /// Each stage feeds the next; the output of the last stage leaves the pipeline.
+    Series,
+    /// Like `Series`, but the output of the last stage is also fed back into the first.
+    Ring,
+}
+
+#[derive(Debug)]
+pub enum PipelineError {
+    Vm { stage: usize, error: VmError },
+    /// Every stage is waiting for input that will never come.
+    Stalled,
+    /// `best_phases` was asked for more orderings than can be counted.
+    TooManyOrderings,
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipelineError::Vm { stage, error } => write!(f, "stage {}: {}", stage, error),
+            PipelineError::Stalled => write!(f, "every stage is waiting for input"),
+            PipelineError::TooManyOrderings => write!(f, "too many phase orderings to search"),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PipelineError::Vm { error, .. } => Some(error),
+            PipelineError::Stalled | PipelineError::TooManyOrderings => None,
+        }
+    }
+}
+
+/// Computers chained output to input, like the amplifiers of day 7.
+pub struct Pipeline<M: Memory = FlatMemory> {
+    stages: Vec<Computer<M>>,
+    topology: Topology,
+}
+
+impl Pipeline {
+    /// One stage per phase, each running its own copy of `program` with its phase setting
+    /// as first input.
+    pub fn new(program: &[i128], phases: &[i128], topology: Topology) -> Pipeline {
+        let stages = phases
+            .iter()
+            .map(|&phase| {
+                let mut stage = Computer::new(program);
+                stage.add_input_128(phase);
+                stage
+            })
+            .collect();
+        Pipeline::from_stages(stages, topology)
+    }
+}
+
+impl<M: Memory> Pipeline<M> {
+    pub fn from_stages(stages: Vec<Computer<M>>, topology: Topology) -> Pipeline<M> {
+        Pipeline { stages, topology }
+    }
+
+    pub fn stages(&self) -> &[Computer<M>] {
+        &self.stages
+    }
+
+    pub fn into_stages(self) -> Vec<Computer<M>> {
+        self.stages
+    }
+
+    /// Feeds `input` to the first stage and runs the stages in turn until the last one halts.
+    ///
+    /// Returns every value output by the last stage, in order; with `Topology::Ring` these are
+    /// the signals fed back and the last one is the final signal.
+    pub fn run(&mut self, input: &[M::Word]) -> Result<Vec<M::Word>, PipelineError> {
+        let mut signals = Vec::new();
+        if self.stages.is_empty() {
+            return Ok(signals);
+        }
+        for value in input {
+            self.stages[0].add_input_word(value.clone());
+        }
+
+        let last = self.stages.len() - 1;
+        loop {
+            let mut moved = false;
+            for index in 0..=last {
+                let state = self.stages[index]
+                    .run()
+                    .map_err(|error| PipelineError::Vm {
+                        stage: index,
+                        error,
+                    })?;
+                let output = self.stages[index].get_all_output();
+                moved |= !output.is_empty();
+                if index == last {
+                    if self.topology == Topology::Ring {
+                        for value in &output {
+                            self.stages[0].add_input_word(value.clone());
+                        }
+                    }
+                    signals.extend(output);
+                    if state == State::Done {
+                        return Ok(signals);
+                    }
+                } else {
+                    for value in output {
+                        self.stages[index + 1].add_input_word(value);
+                    }
+                }
+            }
+            if !moved {
+                return Err(PipelineError::Stalled);
+            }
+        }
+    }
+}
+
+/// Number of orderings a search thread claims at a time.
+const CHUNK: usize = 64;
+
+/// The ordering of `length` distinct elements of `set` with the given lexicographic rank.
+fn nth_ordering(set: &[i128], length: usize, mut rank: usize) -> Vec<i128> {
+    let mut left: Vec<i128> = set.to_vec();
+    let mut ordering = Vec::with_capacity(length);
+    for position in 0..length {
+        // orderings sharing the same element at this position
+        let block = (1..length - position).map(|i| left.len() - i).product::<usize>();
+        ordering.push(left.remove(rank / block));
+        rank %= block;
+    }
+    ordering
+}
+
+/// Tries every ordering of `length` distinct phases taken from `phases` on all CPU cores, each
+/// as a `Pipeline` of `program` fed a single 0, and returns the ordering that gives the highest
+/// final signal together with that signal.
+///
+/// Returns `None` when there is no ordering to try, `TooManyOrderings` when their number does
+/// not fit in a `usize`, and the first error met otherwise. Ties go to the ordering that comes
+/// first lexicographically.
+pub fn best_phases(
+    program: &[i128],
+    phases: &[i128],
+    length: usize,
+    topology: Topology,
+) -> Result<Option<(Vec<i128>, i128)>, PipelineError> {
+    if length == 0 || length > phases.len() {
+        return Ok(None);
+    }
+    let count = (0..length)
+        .try_fold(1usize, |count, i| count.checked_mul(phases.len() - i))
+        .ok_or(PipelineError::TooManyOrderings)?;
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+
+    let next = AtomicUsize::new(0);
+    let error = Mutex::new(None);
+    let results: Vec<Option<(i128, usize)>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut best: Option<(i128, usize)> = None;
+                    loop {
+                        let start = next.fetch_add(CHUNK, Ordering::SeqCst);
+                        if start >= count {
+                            return best;
+                        }
+                        for rank in start..count.min(start + CHUNK) {
+                            let ordering = nth_ordering(phases, length, rank);
+                            let signal = Pipeline::new(program, &ordering, topology)
+                                .run(&[0])
+                                .map(|signals| signals.last().cloned());
+                            match signal {
+                                Ok(Some(signal)) => {
+                                    if best.is_none_or(|(s, r)| (signal, r) > (s, rank)) {
+                                        best = Some((signal, rank));
+                                    }
+                                }
+                                Ok(None) => {}
+                                Err(err) => {
+                                    error.lock().unwrap().get_or_insert(err);
+                                    next.store(count, Ordering::SeqCst);
+                                    return best;
+                                }
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+
+    if let Some(err) = error.into_inner().unwrap() {
+        return Err(err);
+    }
+    Ok(results
+        .into_iter()
+        .flatten()
+        .max_by(|(s1, r1), (s2, r2)| s1.cmp(s2).then(r2.cmp(r1)))
+        .map(|(signal, rank)| (nth_ordering(phases, length, rank), signal)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_series() {
+        // day 7 example: each stage outputs its input * 10 + phase
+        let program = [3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+        let mut pipeline = Pipeline::new(&program, &[4, 3, 2, 1, 0], Topology::Series);
+        assert_eq!(pipeline.run(&[0]).unwrap(), vec![43210]);
+        assert_eq!(pipeline.stages().len(), 5);
+    }
+
+    #[test]
+    fn test_ring() {
+        let program = [
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let mut pipeline = Pipeline::new(&program, &[9, 8, 7, 6, 5], Topology::Ring);
+        let signals = pipeline.run(&[0]).unwrap();
+        assert_eq!(signals.len(), 5);
+        assert_eq!(signals.last(), Some(&139629729));
+
+        let mut pipeline = Pipeline::new(&program, &[9, 8, 7, 6, 5], Topology::Series);
+        assert!(matches!(pipeline.run(&[0]), Err(PipelineError::Stalled)));
+    }
+
+    #[test]
+    fn test_nth_ordering() {
+        let orderings: Vec<Vec<i128>> = (0..6).map(|r| nth_ordering(&[1, 2, 3], 2, r)).collect();
+        assert_eq!(
+            orderings,
+            vec![vec![1, 2], vec![1, 3], vec![2, 1], vec![2, 3], vec![3, 1], vec![3, 2]]
+        );
+    }
+
+    #[test]
+    fn test_best_phases() {
+        let program = [3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+        let best = best_phases(&program, &[0, 1, 2, 3, 4], 5, Topology::Series).unwrap();
+        assert_eq!(best, Some((vec![4, 3, 2, 1, 0], 43210)));
+        let best = best_phases(&program, &[7, 0, 1, 2, 3, 4], 3, Topology::Series).unwrap();
+        assert_eq!(best, Some((vec![7, 4, 3], 743)));
+        assert_eq!(best_phases(&program, &[1], 2, Topology::Series).unwrap(), None);
+
+        let phases: Vec<i128> = (0..30).collect();
+        assert!(matches!(
+            best_phases(&program, &phases, 30, Topology::Series),
+            Err(PipelineError::TooManyOrderings)
+        ));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::computer::{Instruction, InstructionType};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Execution counts for one instruction address.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct HotSpot {
+    pub address: i128,
+    /// The instruction last executed at `address`; self-modifying code may have run others.
+    pub itype: InstructionType,
+    pub count: u64,
+    /// How many taken jumps landed on `address`.
+    pub jumps_in: u64,
+}
+
+/// What a program did while profiling was on, see `Computer::set_profiling`.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Profile {
+    instructions: u64,
+    opcodes: BTreeMap<i128, (InstructionType, u64)>,
+    addresses: BTreeMap<i128, (InstructionType, u64)>,
+    jump_targets: BTreeMap<i128, u64>,
+    high_water: Option<i128>,
+    inputs: u64,
+    outputs: u64,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    /// Counts `instr`, after which execution continues at `next` (`None` once halted).
+    pub(crate) fn record(
+        &mut self,
+        instr: &Instruction,
+        next: Option<i128>,
+        written: Option<i128>,
+        inputs: u64,
+        outputs: u64,
+    ) {
+        let itype = instr.itype();
+        self.instructions += 1;
+        self.opcodes.entry(itype.code()).or_insert((itype, 0)).1 += 1;
+        let spot = self.addresses.entry(instr.address()).or_insert((itype, 0));
+        spot.0 = itype;
+        spot.1 += 1;
+        let fallthrough = instr.address() + 1 + itype.arity() as i128;
+        if let Some(target) = next.filter(|&next| next != fallthrough) {
+            *self.jump_targets.entry(target).or_insert(0) += 1;
+        }
+        if let Some(address) = written {
+            self.high_water = Some(self.high_water.map_or(address, |h| h.max(address)));
+        }
+        self.inputs += inputs;
+        self.outputs += outputs;
+    }
+
+    /// Total number of executed instructions.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Executed instructions per opcode, in opcode order.
+    pub fn opcode_counts(&self) -> Vec<(InstructionType, u64)> {
+        self.opcodes.values().cloned().collect()
+    }
+
+    pub fn count_at(&self, address: i128) -> u64 {
+        self.addresses.get(&address).map_or(0, |spot| spot.1)
+    }
+
+    /// Addresses taken jumps went to, with how often each was reached that way.
+    pub fn jump_targets(&self) -> &BTreeMap<i128, u64> {
+        &self.jump_targets
+    }
+
+    /// Highest address the program wrote to.
+    pub fn high_water_mark(&self) -> Option<i128> {
+        self.high_water
+    }
+
+    /// Values the program consumed.
+    pub fn inputs(&self) -> u64 {
+        self.inputs
+    }
+
+    /// Values the program produced.
+    pub fn outputs(&self) -> u64 {
+        self.outputs
+    }
+
+    /// Every executed address, in address order.
+    pub fn hot_spots(&self) -> Vec<HotSpot> {
+        self.addresses
+            .iter()
+            .map(|(&address, &(itype, count))| HotSpot {
+                address,
+                itype,
+                count,
+                jumps_in: self.jump_targets.get(&address).cloned().unwrap_or(0),
+            })
+            .collect()
+    }
+
+    /// A summary followed by one line per executed address with its count, its share of all
+    /// executed instructions and how many jumps landed on it.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        let high_water = self
+            .high_water
+            .map_or_else(|| "-".to_string(), |h| h.to_string());
+        let _ = writeln!(report, "instructions {}", self.instructions);
+        let _ = writeln!(report, "inputs {}", self.inputs);
+        let _ = writeln!(report, "outputs {}", self.outputs);
+        let _ = writeln!(report, "high water mark {}", high_water);
+        for (itype, count) in self.opcode_counts() {
+            let _ = writeln!(report, "{:>6} {:>12}", itype.mnemonic(), count);
+        }
+        let _ = writeln!(
+            report,
+            "\n{:>8} {:>12} {:>7}  {:<6} jumps in",
+            "address", "count", "%", "instr"
+        );
+        let total = self.instructions.max(1) as f64;
+        for spot in self.hot_spots() {
+            let jumps_in = if spot.jumps_in > 0 {
+                spot.jumps_in.to_string()
+            } else {
+                String::new()
+            };
+            let line = format!(
+                "{:>8} {:>12} {:>7.2}  {:<6} {}",
+                spot.address,
+                spot.count,
+                100.0 * spot.count as f64 / total,
+                spot.itype.mnemonic(),
+                jumps_in
+            );
+            let _ = writeln!(report, "{}", line.trim_end());
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::computer::{Computer, State};
+
+    #[test]
+    fn test_profile() {
+        // counts down from the input, printing every value
+        let program = assemble(
+            "
+                    in [n]
+            loop:   out [n]
+                    add [n], #-1, [n]
+                    jt [n], #loop
+                    hlt
+            n:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut computer = Computer::new(&program);
+        computer.set_profiling(true);
+        assert_eq!(computer.run().unwrap(), State::WaitingInput);
+        assert_eq!(computer.profile().unwrap().instructions(), 0);
+        computer.add_input(3);
+        assert_eq!(computer.run().unwrap(), State::Done);
+
+        let profile = computer.profile().unwrap();
+        assert_eq!(profile.instructions(), 1 + 3 * 3 + 1);
+        assert_eq!((profile.inputs(), profile.outputs()), (1, 3));
+        assert_eq!(profile.high_water_mark(), Some(12));
+        assert_eq!(profile.count_at(2), 3);
+        assert_eq!(
+            profile.jump_targets().iter().collect::<Vec<_>>(),
+            vec![(&2, &2)]
+        );
+        use InstructionType::*;
+        let opcodes = vec![
+            (Add, 3),
+            (Input, 1),
+            (Output, 3),
+            (JumpIfTrue, 3),
+            (Exit, 1),
+        ];
+        assert_eq!(profile.opcode_counts(), opcodes);
+
+        let report = profile.report();
+        assert!(report.starts_with("instructions 11\ninputs 1\noutputs 3\nhigh water mark 12\n"));
+        assert!(report.contains("\n       2            3   27.27  out    2\n"));
+        assert!(report.ends_with("\n      11            1    9.09  hlt\n"));
+
+        assert_eq!(computer.take_profile().unwrap().instructions(), 11);
+        assert_eq!(computer.profile().unwrap().instructions(), 0);
+    }
+}
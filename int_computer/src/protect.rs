@@ -0,0 +1,229 @@
+use std::fmt;
+use std::ops::Range;
+
+/// Limits on what a program may do to memory, see `Computer::set_memory_policy`.
+///
+/// Only accesses made by executing instructions are checked; `Computer::memwrite` and
+/// `Computer::memread` are left alone so the host can still patch and inspect memory.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct MemoryPolicy {
+    max_address: Option<i128>,
+    max_footprint: Option<usize>,
+    read_only: Vec<Range<i128>>,
+    protect_image: bool,
+}
+
+impl MemoryPolicy {
+    pub fn new() -> MemoryPolicy {
+        MemoryPolicy::default()
+    }
+
+    /// Faults on any read or write above `address`.
+    pub fn max_address(mut self, address: i128) -> MemoryPolicy {
+        self.max_address = Some(address);
+        self
+    }
+
+    /// Faults on a write that would take the highest address written more than `words` words
+    /// past the program image, bounding how much memory the program can make the computer
+    /// allocate. A write far above the others is charged for the gap below it.
+    pub fn max_footprint(mut self, words: usize) -> MemoryPolicy {
+        self.max_footprint = Some(words);
+        self
+    }
+
+    /// Faults on writes to `range`; may be given several times.
+    pub fn read_only(mut self, range: Range<i128>) -> MemoryPolicy {
+        self.read_only.push(range);
+        self
+    }
+
+    /// Faults on writes to the program image, the words the computer was created with.
+    pub fn protect_image(mut self) -> MemoryPolicy {
+        self.protect_image = true;
+        self
+    }
+}
+
+/// Why a memory access was refused by the `MemoryPolicy`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum MemoryFault {
+    AddressLimit { address: i128, limit: i128 },
+    FootprintLimit { address: i128, limit: usize },
+    ReadOnly { address: i128 },
+    ImageWrite { address: i128 },
+}
+
+impl MemoryFault {
+    pub fn address(&self) -> i128 {
+        match self {
+            MemoryFault::AddressLimit { address, .. }
+            | MemoryFault::FootprintLimit { address, .. }
+            | MemoryFault::ReadOnly { address }
+            | MemoryFault::ImageWrite { address } => *address,
+        }
+    }
+}
+
+impl fmt::Display for MemoryFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryFault::AddressLimit { address, limit } => {
+                write!(f, "address {} is above the limit of {}", address, limit)
+            }
+            MemoryFault::FootprintLimit { address, limit } => write!(
+                f,
+                "writing address {} would take memory past the limit of {} words",
+                address, limit
+            ),
+            MemoryFault::ReadOnly { address } => {
+                write!(f, "write to read-only address {}", address)
+            }
+            MemoryFault::ImageWrite { address } => {
+                write!(f, "write to address {} of the program image", address)
+            }
+        }
+    }
+}
+
+/// A policy together with where the program image ends, needed to enforce it.
+#[derive(Debug, Clone)]
+pub(crate) struct Guard {
+    policy: MemoryPolicy,
+    image_end: i128,
+}
+
+impl Guard {
+    pub(crate) fn new(policy: MemoryPolicy, image_end: i128) -> Guard {
+        Guard { policy, image_end }
+    }
+
+    pub(crate) fn policy(&self) -> &MemoryPolicy {
+        &self.policy
+    }
+
+    pub(crate) fn check_read(&self, address: i128) -> Result<(), MemoryFault> {
+        match self.policy.max_address {
+            Some(limit) if address > limit => Err(MemoryFault::AddressLimit { address, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks a write to memory whose `end` is one past the highest address written so far.
+    pub(crate) fn check_write(&self, address: i128, end: i128) -> Result<(), MemoryFault> {
+        self.check_read(address)?;
+        let in_image = (0..self.image_end).contains(&address);
+        if self.policy.protect_image && in_image {
+            return Err(MemoryFault::ImageWrite { address });
+        }
+        if self.policy.read_only.iter().any(|r| r.contains(&address)) {
+            return Err(MemoryFault::ReadOnly { address });
+        }
+        if let Some(limit) = self.policy.max_footprint {
+            let end = end.max(address.saturating_add(1));
+            let footprint = end.saturating_sub(self.image_end.max(0));
+            if footprint > limit as i128 {
+                return Err(MemoryFault::FootprintLimit { address, limit });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::computer::{Computer, State, VmError};
+    use crate::memory::{Memory, SparseMemory};
+
+    /// Stores its inputs at `base`, `base + stride`, ...; 9 words long.
+    fn filler_program(base: i128, stride: i128) -> Vec<i128> {
+        assemble(&format!(
+            "
+                    arb #{}
+            loop:   in rb+0
+                    arb #{}
+                    jt #1, #loop
+            ",
+            base, stride
+        ))
+        .unwrap()
+    }
+
+    fn filler(base: i128) -> Computer {
+        Computer::new(&filler_program(base, 1))
+    }
+
+    fn fault<M: Memory>(computer: &mut Computer<M>) -> MemoryFault {
+        match computer.run() {
+            Err(VmError::Fault { fault, .. }) => fault,
+            other => panic!("expected a memory fault, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_footprint_counts_highest_address() {
+        let mut computer = filler(9).with_memory_policy(MemoryPolicy::new().max_footprint(100));
+        for _ in 0..200 {
+            computer.add_input(7);
+        }
+        let expected = MemoryFault::FootprintLimit {
+            address: 109,
+            limit: 100,
+        };
+        assert_eq!(fault(&mut computer), expected);
+        assert_eq!((computer.memread(108), computer.memread(109)), (7, 0));
+        assert_eq!(computer.memory().end(), 109);
+
+        // a few writes far apart would otherwise grow the vector to gigabytes
+        let limit = 1 << 20;
+        let program = filler_program(60_000, 60_000);
+        let mut computer =
+            Computer::new(&program).with_memory_policy(MemoryPolicy::new().max_footprint(limit));
+        for _ in 0..100 {
+            computer.add_input(7);
+        }
+        assert_eq!(fault(&mut computer).address(), 1_080_000);
+        assert!(computer.memory().end() <= 9 + limit as i128);
+    }
+
+    #[test]
+    fn test_memory_policies() {
+        let memory = SparseMemory::from_program(&filler_program(9, 1));
+        let mut computer =
+            Computer::with_memory(memory).with_memory_policy(MemoryPolicy::new().max_footprint(2));
+        for _ in 0..3 {
+            computer.add_input(7);
+        }
+        let expected = MemoryFault::FootprintLimit {
+            address: 11,
+            limit: 2,
+        };
+        assert_eq!(fault(&mut computer), expected);
+        assert_eq!((computer.memread(10), computer.memread(11)), (7, 0));
+        assert_eq!(computer.instruction_pointer(), 2);
+        assert!(computer.has_input());
+
+        let mut computer =
+            filler(1 << 40).with_memory_policy(MemoryPolicy::new().max_address(1 << 20));
+        computer.add_input(7);
+        let expected = MemoryFault::AddressLimit {
+            address: 1 << 40,
+            limit: 1 << 20,
+        };
+        assert_eq!(fault(&mut computer), expected);
+
+        let mut computer = filler(5).with_memory_policy(MemoryPolicy::new().protect_image());
+        computer.add_input(7);
+        assert_eq!(fault(&mut computer), MemoryFault::ImageWrite { address: 5 });
+
+        let mut computer = filler(15).with_memory_policy(MemoryPolicy::new().read_only(0..20));
+        computer.add_input(7);
+        assert_eq!(fault(&mut computer), MemoryFault::ReadOnly { address: 15 });
+
+        computer.clear_memory_policy();
+        assert_eq!(computer.run().unwrap(), State::WaitingInput);
+        assert_eq!(computer.memread(15), 7);
+    }
+}
@@ -0,0 +1,369 @@
+use crate::computer::{Computer, Instruction, InstructionType, Snapshot, MAX_PARAMS};
+use crate::memory::Memory;
+use crate::word::Word;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+const MAGIC: &str = "intcode-vm";
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    NotASaveFile,
+    UnsupportedVersion(String),
+    ChecksumMismatch,
+    Corrupt { line: usize, reason: String },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "i/o error: {}", err),
+            LoadError::NotASaveFile => write!(f, "not an {} save file", MAGIC),
+            LoadError::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported save format version {}, expected {}",
+                v, FORMAT_VERSION
+            ),
+            LoadError::ChecksumMismatch => write!(f, "checksum mismatch, the file is corrupt"),
+            LoadError::Corrupt { line, reason } => write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> LoadError {
+        LoadError::Io(err)
+    }
+}
+
+/// 64 bit FNV-1a, enough to notice truncated or hand-edited files.
+fn checksum(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn join<W: Word>(values: &[W]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Splits memory into runs of consecutive addresses.
+fn runs<W: Word>(words: Vec<(i128, W)>) -> Vec<(i128, Vec<W>)> {
+    let mut runs: Vec<(i128, Vec<W>)> = Vec::new();
+    for (address, value) in words {
+        match runs.last_mut() {
+            Some((start, values)) if *start + values.len() as i128 == address => {
+                values.push(value)
+            }
+            _ => runs.push((address, vec![value])),
+        }
+    }
+    runs
+}
+
+impl<M: Memory> Snapshot<M> {
+    /// Serializes the snapshot in the versioned text format:
+    ///
+    /// ```text
+    /// intcode-vm 1
+    /// ip 25
+    /// rb 1035
+    /// pending 25 203 1035 0 0
+    /// input 5,6
+    /// output
+    /// memory 0 1102,34463338,34463338,63
+    /// checksum 8c3f0f2b5e8ae0a1
+    /// ```
+    ///
+    /// `pending` is `-` unless an input instruction is waiting for a value, in which case it
+    /// holds its address, opcode and resolved operands. The checksum covers every line before it.
+    ///
+    /// Fails with `io::ErrorKind::InvalidInput` if the waiting instruction is a custom opcode,
+    /// which `from_text` could not load back.
+    pub fn to_text(&self) -> io::Result<String> {
+        if let Some(instr) = self.last_instr.filter(|i| i.itype != InstructionType::Input) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "cannot save while custom opcode {} at {} waits for input",
+                    instr.opcode, instr.address
+                ),
+            ));
+        }
+        let mut text = format!("{} {}\n", MAGIC, FORMAT_VERSION);
+        text.push_str(&format!("ip {}\n", self.instruction_pointer));
+        text.push_str(&format!("rb {}\n", self.relative_base));
+        match &self.last_instr {
+            Some(instr) => text.push_str(&format!(
+                "pending {} {} {}\n",
+                instr.address,
+                instr.opcode,
+                instr
+                    .operands
+                    .iter()
+                    .map(|o| o.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            )),
+            None => text.push_str("pending -\n"),
+        }
+        let input: Vec<M::Word> = self.input.iter().cloned().collect();
+        let output: Vec<M::Word> = self.output.iter().cloned().collect();
+        text.push_str(format!("input {}", join(&input)).trim_end());
+        text.push('\n');
+        text.push_str(format!("output {}", join(&output)).trim_end());
+        text.push('\n');
+        for (start, values) in runs(self.memory.words()) {
+            text.push_str(&format!("memory {} {}\n", start, join(&values)));
+        }
+        let sum = checksum(&text);
+        text.push_str(&format!("checksum {:016x}\n", sum));
+        Ok(text)
+    }
+
+    pub fn from_text(text: &str) -> Result<Snapshot<M>, LoadError> {
+        let header = text.lines().next().unwrap_or("");
+        match header.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            [MAGIC, version] if *version == FORMAT_VERSION.to_string() => {}
+            [MAGIC, version] => return Err(LoadError::UnsupportedVersion(version.to_string())),
+            _ => return Err(LoadError::NotASaveFile),
+        }
+
+        let body_end = text.trim_end().rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let (body, trailer) = text.split_at(body_end);
+        match trailer.trim().strip_prefix("checksum ") {
+            Some(sum) if u64::from_str_radix(sum, 16).ok() == Some(checksum(body)) => {}
+            Some(_) => return Err(LoadError::ChecksumMismatch),
+            None => {
+                return Err(LoadError::Corrupt {
+                    line: body.lines().count() + 1,
+                    reason: "missing checksum".to_string(),
+                })
+            }
+        }
+
+        let mut lines = body.lines().enumerate().skip(1);
+        let mut field = |key: &str| -> Result<(usize, String), LoadError> {
+            match lines.next() {
+                Some((i, line)) => {
+                    let mut parts = line.splitn(2, ' ');
+                    if parts.next() == Some(key) {
+                        Ok((i + 1, parts.next().unwrap_or("").trim().to_string()))
+                    } else {
+                        Err(corrupt(i + 1, &format!("expected `{}`", key)))
+                    }
+                }
+                None => Err(corrupt(0, &format!("missing `{}`", key))),
+            }
+        };
+
+        let (line, ip) = field("ip")?;
+        let instruction_pointer = number(line, &ip)?;
+        let (line, rb) = field("rb")?;
+        let relative_base = number(line, &rb)?;
+        let (line, pending) = field("pending")?;
+        let last_instr = if pending == "-" {
+            None
+        } else {
+            Some(parse_pending(line, &pending)?)
+        };
+        let (line, input) = field("input")?;
+        let input: VecDeque<M::Word> = numbers(line, &input)?.into_iter().collect();
+        let (line, output) = field("output")?;
+        let output: VecDeque<M::Word> = numbers(line, &output)?.into_iter().collect();
+
+        let mut memory: Option<M> = None;
+        for (i, text) in lines {
+            let line = i + 1;
+            let run = text
+                .strip_prefix("memory ")
+                .ok_or_else(|| corrupt(line, "expected `memory`"))?;
+            let mut parts = run.splitn(2, ' ');
+            let start = number(line, parts.next().unwrap_or(""))?;
+            let values = numbers(line, parts.next().unwrap_or(""))?;
+            match memory.as_mut() {
+                None if start == 0 => memory = Some(M::from_program(&values)),
+                _ => {
+                    let m = memory.get_or_insert_with(|| M::from_program(&[]));
+                    for (address, value) in (start..).zip(values) {
+                        m.write(address, value);
+                    }
+                }
+            }
+        }
+
+        Ok(Snapshot {
+            memory: memory.unwrap_or_else(|| M::from_program(&[])),
+            output,
+            input,
+            instruction_pointer,
+            last_instr,
+            relative_base,
+        })
+    }
+}
+
+fn corrupt(line: usize, reason: &str) -> LoadError {
+    LoadError::Corrupt {
+        line,
+        reason: reason.to_string(),
+    }
+}
+
+fn number<T: std::str::FromStr>(line: usize, text: &str) -> Result<T, LoadError> {
+    text.trim()
+        .parse::<T>()
+        .map_err(|_| corrupt(line, &format!("invalid number `{}`", text)))
+}
+
+fn numbers<T: std::str::FromStr>(line: usize, text: &str) -> Result<Vec<T>, LoadError> {
+    if text.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    text.split(',').map(|v| number(line, v)).collect()
+}
+
+fn parse_pending(line: usize, text: &str) -> Result<Instruction, LoadError> {
+    let values = text
+        .split_whitespace()
+        .map(|v| number(line, v))
+        .collect::<Result<Vec<i128>, LoadError>>()?;
+    if values.len() != 2 + MAX_PARAMS {
+        return Err(corrupt(line, "pending instruction needs address, opcode and operands"));
+    }
+    if InstructionType::from_opcode(values[1]) != Some(InstructionType::Input) {
+        return Err(corrupt(line, "only an input instruction can be pending"));
+    }
+    let mut operands = [0; MAX_PARAMS];
+    operands.copy_from_slice(&values[2..]);
+    Ok(Instruction {
+        itype: InstructionType::Input,
+        operands,
+        address: values[0],
+        opcode: values[1],
+    })
+}
+
+impl<M: Memory + Clone> Computer<M> {
+    /// Writes the machine state to `out`, see `Snapshot::to_text` for the format.
+    pub fn save<W: Write>(&self, mut out: W) -> io::Result<()> {
+        out.write_all(self.snapshot().to_text()?.as_bytes())
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save(std::fs::File::create(path)?)
+    }
+}
+
+impl Computer {
+    /// Reads a machine written by `save`, ready to `run` from where it stopped. Only the
+    /// built-in opcodes are available; custom ones must be registered again.
+    pub fn restore_from<R: Read>(mut input: R) -> Result<Computer, LoadError> {
+        let mut text = String::new();
+        input.read_to_string(&mut text)?;
+        Ok(Computer::from_snapshot(Snapshot::from_text(&text)?))
+    }
+
+    pub fn restore_from_file<P: AsRef<Path>>(path: P) -> Result<Computer, LoadError> {
+        Computer::restore_from(std::fs::File::open(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::State;
+    use crate::memory::SparseMemory;
+
+    fn paused() -> Computer {
+        // echoes inputs multiplied by 3, with a scratch word far past the program
+        let mut computer = Computer::new(&[3, 100, 1002, 100, 3, 5000, 4, 5000, 1105, 1, 0]);
+        computer.add_input(2);
+        assert_eq!(computer.run().unwrap(), State::WaitingInput);
+        computer.add_input(9);
+        computer.add_input(4);
+        computer
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut original = paused();
+        let mut saved = Vec::new();
+        original.save(&mut saved).unwrap();
+
+        let mut loaded = Computer::restore_from(&saved[..]).unwrap();
+        assert_eq!(loaded.instruction_pointer(), original.instruction_pointer());
+        loaded.run().unwrap();
+        original.run().unwrap();
+        assert_eq!(loaded.get_all_output(), vec![6, 27, 12]);
+        assert_eq!(original.get_all_output(), vec![6, 27, 12]);
+
+        let text = String::from_utf8(saved).unwrap();
+        let sparse = Snapshot::<SparseMemory>::from_text(&text).unwrap();
+        assert_eq!(sparse.to_text().unwrap(), text);
+    }
+
+    #[test]
+    fn test_save_rejects_custom_pending() {
+        use crate::computer::VmError;
+        use crate::memory::FlatMemory;
+        use crate::opcode::{Exec, Flow, Opcode};
+        // `get a`: like `in`, under another opcode
+        fn get(exec: &mut Exec<FlatMemory>) -> Result<Flow, VmError> {
+            match exec.input() {
+                Some(value) => exec.write(0, value).map(|_| Flow::Continue),
+                None => Ok(Flow::WaitInput),
+            }
+        }
+        let mut computer = Computer::new(&[20, 3, 99, 0]);
+        computer.register_opcode(Opcode::new(20, "get", 1, &[0], get));
+        assert_eq!(computer.run().unwrap(), State::WaitingInput);
+        let err = computer.save(Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_load_rejects_bad_files() {
+        let mut saved = Vec::new();
+        paused().save(&mut saved).unwrap();
+        let text = String::from_utf8(saved).unwrap();
+
+        let load = |text: &str| Computer::restore_from(text.as_bytes()).err().unwrap();
+        assert!(matches!(load("hello\n"), LoadError::NotASaveFile));
+        assert!(matches!(
+            load(&text.replacen("intcode-vm 1", "intcode-vm 7", 1)),
+            LoadError::UnsupportedVersion(ref v) if v == "7"
+        ));
+        assert!(matches!(
+            load(&text.replacen("input 9,4", "input 9,5", 1)),
+            LoadError::ChecksumMismatch
+        ));
+        let truncated = &text[..text.len() / 2];
+        assert!(matches!(
+            load(truncated),
+            LoadError::ChecksumMismatch | LoadError::Corrupt { .. }
+        ));
+
+        let body = text.replacen("ip ", "ipx ", 1);
+        let body = &body[..body.rfind("checksum").unwrap()];
+        let forged = format!("{}checksum {:016x}\n", body, checksum(body));
+        assert!(matches!(load(&forged), LoadError::Corrupt { line: 2, .. }));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::computer::{Instruction, MemoryWrite};
+use std::fmt::Display;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// An instruction executed by `Computer::step`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TraceEvent<V = i128> {
+    pub address: i128,
+    pub instruction: Instruction,
+    /// Values found at each operand position before the instruction ran.
+    pub values: Vec<V>,
+    /// Every word the instruction wrote, in write order.
+    pub writes: Vec<MemoryWrite<V>>,
+}
+
+pub trait Tracer<V = i128> {
+    fn trace(&mut self, event: &TraceEvent<V>);
+}
+
+impl<V, F: FnMut(&TraceEvent<V>)> Tracer<V> for F {
+    fn trace(&mut self, event: &TraceEvent<V>) {
+        self(event)
+    }
+}
+
+/// Writes one line per executed instruction, e.g.
+/// `    12: add [3]=4, [7]=5, [3]=4 ; [3] 4 -> 9`.
+/// Write errors are ignored so that tracing never interferes with execution.
+pub struct TextTracer<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TextTracer<W> {
+    pub fn new(out: W) -> TextTracer<W> {
+        TextTracer { out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+pub fn format_event<V: Display>(event: &TraceEvent<V>) -> String {
+    let operands: Vec<String> = event
+        .instruction
+        .operands()
+        .iter()
+        .zip(event.values.iter())
+        .map(|(pos, value)| format!("[{}]={}", pos, value))
+        .collect();
+    let mut line = format!(
+        "{:>6}: {} {}",
+        event.address,
+        event.instruction.itype().mnemonic(),
+        operands.join(", ")
+    );
+    for write in &event.writes {
+        line.push_str(&format!(" ; [{}] {} -> {}", write.address, write.old, write.new));
+    }
+    line.trim_end().to_string()
+}
+
+impl<W: Write, V: Display> Tracer<V> for TextTracer<W> {
+    fn trace(&mut self, event: &TraceEvent<V>) {
+        let _ = writeln!(self.out, "{}", format_event(event));
+    }
+}
+
+/// Collects events in memory. Clones share the same buffer, so keep one to read the trace
+/// after handing the other to `Computer::set_tracer`.
+#[derive(Clone)]
+pub struct VecTracer<V = i128> {
+    events: Arc<Mutex<Vec<TraceEvent<V>>>>,
+}
+
+impl<V> Default for VecTracer<V> {
+    fn default() -> VecTracer<V> {
+        VecTracer {
+            events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl<V: Clone> VecTracer<V> {
+    pub fn new() -> VecTracer<V> {
+        VecTracer::default()
+    }
+
+    pub fn events(&self) -> Vec<TraceEvent<V>> {
+        self.events.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+}
+
+impl<V: Clone> Tracer<V> for VecTracer<V> {
+    fn trace(&mut self, event: &TraceEvent<V>) {
+        self.events.lock().unwrap().push(event.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::{Computer, InstructionType};
+
+    #[test]
+    fn test_trace() {
+        let mut computer = Computer::new(&[3, 9, 1001, 9, 5, 9, 4, 9, 99, 0]);
+        let tracer = VecTracer::new();
+        computer.set_tracer(tracer.clone());
+        computer.add_input(4);
+        computer.run().unwrap();
+
+        let events = tracer.events();
+        let kinds: Vec<InstructionType> = events
+            .iter()
+            .map(|e| e.instruction.itype())
+            .collect();
+        use InstructionType::*;
+        assert_eq!(kinds, vec![Input, Add, Output, Exit]);
+        assert_eq!(events[1].values, vec![4, 5, 4]);
+        assert_eq!(
+            events[1].writes,
+            vec![MemoryWrite {
+                address: 9,
+                old: 4,
+                new: 9
+            }]
+        );
+        assert_eq!(format_event(&events[1]), "     2: add [9]=4, [4]=5, [9]=4 ; [9] 4 -> 9");
+        assert_eq!(format_event(&events[3]), "     8: hlt");
+    }
+
+    #[test]
+    fn test_closure_tracer() {
+        let mut computer = Computer::new(&[104, 7, 99]);
+        let addresses = Arc::new(Mutex::new(Vec::new()));
+        let sink = addresses.clone();
+        computer.set_tracer(move |e: &TraceEvent| sink.lock().unwrap().push(e.address));
+        computer.run().unwrap();
+        assert_eq!(*addresses.lock().unwrap(), vec![0, 2]);
+    }
+}
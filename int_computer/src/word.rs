@@ -0,0 +1,249 @@
+use num_bigint::BigInt;
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+/// Value stored in a memory cell of a `Computer`.
+///
+/// Addresses, the instruction pointer and the relative base stay `i128` whatever the word type;
+/// using a word too large to be one as an address stops with `VmError::AddressOutOfRange`.
+pub trait Word:
+    Clone
+    + PartialEq
+    + PartialOrd
+    + Default
+    + Debug
+    + Display
+    + FromStr
+    + From<i32>
+    + Send
+    + Sync
+    + 'static
+{
+    /// The same value as an `i128`, if it fits.
+    fn to_i128(&self) -> Option<i128>;
+
+    /// The same value as a word, if it fits.
+    fn from_i128(value: i128) -> Option<Self>;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+    fn saturating_add(&self, other: &Self) -> Self;
+    fn saturating_mul(&self, other: &Self) -> Self;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+macro_rules! primitive_word {
+    ($t:ty) => {
+        impl Word for $t {
+            fn to_i128(&self) -> Option<i128> {
+                Some(*self as i128)
+            }
+
+            fn from_i128(value: i128) -> Option<$t> {
+                <$t>::try_from(value).ok()
+            }
+
+            fn checked_add(&self, other: &$t) -> Option<$t> {
+                <$t>::checked_add(*self, *other)
+            }
+
+            fn checked_mul(&self, other: &$t) -> Option<$t> {
+                <$t>::checked_mul(*self, *other)
+            }
+
+            fn wrapping_add(&self, other: &$t) -> $t {
+                <$t>::wrapping_add(*self, *other)
+            }
+
+            fn wrapping_mul(&self, other: &$t) -> $t {
+                <$t>::wrapping_mul(*self, *other)
+            }
+
+            fn saturating_add(&self, other: &$t) -> $t {
+                <$t>::saturating_add(*self, *other)
+            }
+
+            fn saturating_mul(&self, other: &$t) -> $t {
+                <$t>::saturating_mul(*self, *other)
+            }
+        }
+    };
+}
+
+primitive_word!(i64);
+primitive_word!(i128);
+
+/// Never overflows, so every policy gives the exact result.
+impl Word for BigInt {
+    fn to_i128(&self) -> Option<i128> {
+        i128::try_from(self).ok()
+    }
+
+    fn from_i128(value: i128) -> Option<BigInt> {
+        Some(BigInt::from(value))
+    }
+
+    fn checked_add(&self, other: &BigInt) -> Option<BigInt> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &BigInt) -> Option<BigInt> {
+        Some(self * other)
+    }
+
+    fn wrapping_add(&self, other: &BigInt) -> BigInt {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &BigInt) -> BigInt {
+        self * other
+    }
+
+    fn saturating_add(&self, other: &BigInt) -> BigInt {
+        self + other
+    }
+
+    fn saturating_mul(&self, other: &BigInt) -> BigInt {
+        self * other
+    }
+}
+
+/// What `add` and `mul` do when the result does not fit in the word type.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum Overflow {
+    /// Stop with `VmError::Overflow`.
+    #[default]
+    Checked,
+    /// Wrap around in two's complement.
+    Wrapping,
+    /// Clamp to the smallest or largest word.
+    Saturating,
+}
+
+impl Overflow {
+    /// `a + b`, or `None` if it overflows under the `Checked` policy.
+    pub fn add<W: Word>(self, a: &W, b: &W) -> Option<W> {
+        match self {
+            Overflow::Checked => a.checked_add(b),
+            Overflow::Wrapping => Some(a.wrapping_add(b)),
+            Overflow::Saturating => Some(a.saturating_add(b)),
+        }
+    }
+
+    /// `a * b`, or `None` if it overflows under the `Checked` policy.
+    pub fn mul<W: Word>(self, a: &W, b: &W) -> Option<W> {
+        match self {
+            Overflow::Checked => a.checked_mul(b),
+            Overflow::Wrapping => Some(a.wrapping_mul(b)),
+            Overflow::Saturating => Some(a.saturating_mul(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::computer::{Computer, State, VmError};
+    use crate::memory::{FlatMemory, Memory};
+
+    /// Squares 3 seven times, far past what fits in an `i128`.
+    fn squares<W: Word>(overflow: Overflow) -> (Result<State, VmError>, Computer<FlatMemory<W>>) {
+        let program = assemble(
+            "
+            loop:   mul [x], [x], [x]
+                    add [n], #-1, [n]
+                    jt [n], #loop
+                    out [x]
+                    hlt
+            x:      .data 3
+            n:      .data 7
+            ",
+        )
+        .unwrap();
+        let words: Vec<W> = program.iter().map(|&v| W::from_i128(v).unwrap()).collect();
+        let mut computer = Computer::with_memory(FlatMemory::from_program(&words));
+        computer.set_overflow(overflow);
+        (computer.run(), computer)
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        let (result, computer) = squares::<i64>(Overflow::Checked);
+        assert!(matches!(
+            result,
+            Err(VmError::Overflow { ip: 0, opcode: 2 })
+        ));
+        assert_eq!(computer.instruction_pointer(), 0);
+        assert_eq!(computer.memread(14), 3i64.pow(32));
+
+        let (result, mut computer) = squares::<i64>(Overflow::Wrapping);
+        assert_eq!(result.unwrap(), State::Done);
+        let wrapped = (0..7).fold(3i64, |x, _| x.wrapping_mul(x));
+        assert_eq!(computer.get_output(), Some(wrapped));
+
+        let (_, mut computer) = squares::<i128>(Overflow::Saturating);
+        assert_eq!(computer.get_output(), Some(i128::MAX));
+
+        let (result, mut computer) = squares::<BigInt>(Overflow::Checked);
+        assert_eq!(result.unwrap(), State::Done);
+        assert_eq!(computer.get_output(), Some(BigInt::from(3).pow(128)));
+    }
+
+    #[test]
+    fn test_address_out_of_range() {
+        let huge: BigInt = BigInt::from(1) << 200;
+        // the error, and where the instruction pointer was left
+        let run = |program: Vec<BigInt>| {
+            let mut computer = Computer::with_memory(FlatMemory::from_program(&program));
+            let error = computer.run().unwrap_err();
+            (error, computer.instruction_pointer())
+        };
+        let program = |words: &[i128]| -> Vec<BigInt> { words.iter().map(|&v| v.into()).collect() };
+
+        // jt #1, #huge
+        let mut jump = program(&[1105, 1, 0, 99]);
+        jump[2] = huge.clone();
+        assert!(matches!(
+            run(jump),
+            (
+                VmError::AddressOutOfRange {
+                    ip: 0,
+                    opcode: 1105
+                },
+                0
+            )
+        ));
+        // arb #huge
+        let mut adjust = program(&[109, 0, 99]);
+        adjust[1] = huge.clone();
+        assert!(matches!(
+            run(adjust),
+            (VmError::AddressOutOfRange { ip: 0, opcode: 109 }, 0)
+        ));
+        // out [huge]
+        let mut position = program(&[4, 0, 99]);
+        position[1] = huge;
+        assert!(matches!(
+            run(position),
+            (VmError::AddressOutOfRange { ip: 0, opcode: 4 }, 0)
+        ));
+        // a relative operand or base past the i128 range
+        let relative = program(&[109, i128::MAX, 204, 1, 99]);
+        assert!(matches!(
+            run(relative),
+            (VmError::AddressOutOfRange { ip: 2, opcode: 204 }, 2)
+        ));
+        let base = program(&[109, i128::MAX, 109, 1, 99]);
+        assert!(matches!(
+            run(base),
+            (VmError::AddressOutOfRange { ip: 2, opcode: 109 }, 2)
+        ));
+    }
+}
@@ -0,0 +1,324 @@
+//! Behaviour of every built-in opcode in every parameter mode, checked on raw Intcode.
+
+extern crate int_computer;
+use int_computer::computer::{Computer, State, Step, VmError};
+
+/// The relative base is set above the data so relative operands use negative offsets.
+const RB: i128 = 110;
+/// Where the operands of the instruction under test live.
+const DATA: i128 = 100;
+
+const POSITION: i128 = 0;
+const IMMEDIATE: i128 = 1;
+const RELATIVE: i128 = 2;
+const MODES: [i128; 3] = [POSITION, IMMEDIATE, RELATIVE];
+const WRITE_MODES: [i128; 2] = [POSITION, RELATIVE];
+
+/// Program running `arb #RB`, then `code` with the given parameter modes and operands, then
+/// `hlt`. Each operand is stored at `DATA + i` and its parameter set to reach it in its mode;
+/// an immediate operand is its own parameter.
+fn program(code: i128, modes: &[i128], operands: &[i128]) -> Vec<i128> {
+    let mut program = vec![0; DATA as usize + operands.len()];
+    program[0] = 109;
+    program[1] = RB;
+    program[2] = code
+        + (0..)
+            .zip(modes)
+            .map(|(i, m)| m * 10i128.pow(i + 2))
+            .sum::<i128>();
+    for (i, (&mode, &operand)) in modes.iter().zip(operands).enumerate() {
+        let slot = DATA + i as i128;
+        program[3 + i] = match mode {
+            POSITION => slot,
+            IMMEDIATE => operand,
+            _ => slot - RB,
+        };
+        program[slot as usize] = operand;
+    }
+    program[3 + modes.len()] = 99;
+    program
+}
+
+fn run(program: &[i128], input: &[i128]) -> (State, Computer) {
+    let mut computer = Computer::new(program);
+    for &v in input {
+        computer.add_input_128(v);
+    }
+    let state = computer.run().unwrap();
+    (state, computer)
+}
+
+#[test]
+fn test_arithmetic_and_comparisons() {
+    type Expected = fn(i128, i128) -> i128;
+    let cases: [(i128, Expected); 4] = [
+        (1, |a, b| a + b),
+        (2, |a, b| a * b),
+        (7, |a, b| (a < b) as i128),
+        (8, |a, b| (a == b) as i128),
+    ];
+    let pairs = [(3, 4), (-5, 7), (6, 6), (0, -9), (1 << 62, 2)];
+    for &(code, expected) in &cases {
+        for &(a, b) in &pairs {
+            for &m0 in &MODES {
+                for &m1 in &MODES {
+                    for &m2 in &WRITE_MODES {
+                        let program = program(code, &[m0, m1, m2], &[a, b, -1]);
+                        let (state, computer) = run(&program, &[]);
+                        assert_eq!(state, State::Done);
+                        assert_eq!(
+                            computer.memread(DATA + 2),
+                            expected(a, b),
+                            "opcode {} modes {}{}{} on {} and {}",
+                            code,
+                            m0,
+                            m1,
+                            m2,
+                            a,
+                            b
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_input_output() {
+    for &mode in &WRITE_MODES {
+        let (state, computer) = run(&program(3, &[mode], &[0]), &[-42]);
+        assert_eq!(state, State::Done);
+        assert_eq!(computer.memread(DATA), -42, "in in mode {}", mode);
+    }
+    for &mode in &MODES {
+        let (state, mut computer) = run(&program(4, &[mode], &[1 << 100]), &[]);
+        assert_eq!(state, State::Done);
+        assert_eq!(
+            computer.get_all_output(),
+            vec![1 << 100],
+            "out in mode {}",
+            mode
+        );
+    }
+}
+
+#[test]
+fn test_jumps() {
+    // the instruction under test is followed by `out #0`, `hlt`, `out #1`, `hlt`; a taken
+    // jump lands on `out #1`
+    let target = 8;
+    for &(code, jump_if) in &[(5, true), (6, false)] {
+        for &condition in &[0, 1, -3] {
+            for &m0 in &MODES {
+                for &m1 in &MODES {
+                    let mut program = program(code, &[m0, m1], &[condition, target]);
+                    program[5..11].copy_from_slice(&[104, 0, 99, 104, 1, 99]);
+                    let (state, mut computer) = run(&program, &[]);
+                    assert_eq!(state, State::Done);
+                    let taken = (condition != 0) == jump_if;
+                    assert_eq!(
+                        computer.get_all_output(),
+                        vec![taken as i128],
+                        "opcode {} modes {}{} on {}",
+                        code,
+                        m0,
+                        m1,
+                        condition
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_halt() {
+    let (state, mut computer) = run(&[104, 1, 99, 104, 2], &[]);
+    assert_eq!(state, State::Done);
+    assert_eq!(computer.get_all_output(), vec![1]);
+    assert_eq!(computer.instruction_pointer(), 2);
+    assert_eq!(computer.run().unwrap(), State::Done);
+    assert_eq!(computer.step().unwrap(), Step::Done);
+
+    // running off the end of memory halts as well
+    let (state, computer) = run(&[1101, 1, 1, 3], &[]);
+    assert_eq!(state, State::Done);
+    assert_eq!(computer.memread(3), 2);
+}
+
+#[test]
+fn test_relative_base() {
+    // arb in every mode: #5, [data] = 7, rb+(-2) = [10] = -4
+    let program = [109, 5, 9, 9, 209, -2, 99, 0, 0, 7, -4];
+    let (state, computer) = run(&program, &[]);
+    assert_eq!(state, State::Done);
+    assert_eq!(computer.relative_base(), 5 + 7 - 4);
+
+    // relative operands follow every adjustment and may use negative offsets
+    let program = [
+        109, 20, // arb #20
+        21101, 3, 4, 0, // add #3, #4, rb+0
+        109, -5, // arb #-5
+        22201, 5, 5, 1, // add rb+5, rb+5, rb+1
+        204, 1, // out rb+1
+        99,
+    ];
+    let (state, mut computer) = run(&program, &[]);
+    assert_eq!(state, State::Done);
+    assert_eq!(computer.get_all_output(), vec![14]);
+    assert_eq!((computer.memread(20), computer.memread(16)), (7, 14));
+
+    // a relative base far from zero
+    let (_, mut computer) = run(&[109, -1000, 204, 1005, 99, 55], &[]);
+    assert_eq!(computer.get_all_output(), vec![55]);
+}
+
+#[test]
+fn test_spec_programs() {
+    let quine = [
+        109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+    ];
+    let (_, mut computer) = run(&quine, &[]);
+    assert_eq!(computer.get_all_output(), quine.to_vec());
+
+    let (_, mut computer) = run(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0], &[]);
+    assert_eq!(computer.get_all_output(), vec![34915192 * 34915192]);
+
+    // compares the input with 8, in position and immediate mode
+    let programs: [&[i128]; 4] = [
+        &[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8],
+        &[3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8],
+        &[3, 3, 1108, -1, 8, 3, 4, 3, 99],
+        &[3, 3, 1107, -1, 8, 3, 4, 3, 99],
+    ];
+    for &input in &[7, 8, 9] {
+        let expected = [input == 8, input < 8, input == 8, input < 8];
+        for (program, &expected) in programs.iter().zip(&expected) {
+            let (_, mut computer) = run(program, &[input]);
+            assert_eq!(computer.get_all_output(), vec![expected as i128]);
+        }
+    }
+}
+
+#[test]
+fn test_self_modifying_code() {
+    // the classic first example: each instruction's result lands in the program
+    let (_, computer) = run(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50], &[]);
+    assert_eq!(computer.memread(0), 3500);
+
+    // runs its first instruction twice, turning it from an `add` into a `mul` in between
+    let program = [
+        1, 30, 31, 32, // add/mul [30], [31], [32]
+        4, 32, // out [32]
+        1101, 1, 1, 0, // add #1, #1, [0]
+        1001, 33, -1, 33, // add [33], #-1, [33]
+        1005, 33, 0, // jt [33], #0
+        99,
+    ];
+    let mut program = program.to_vec();
+    program.resize(30, 0);
+    program.extend(&[5, 6, 0, 2]);
+    for &cache in &[false, true] {
+        let mut computer = Computer::new(&program);
+        computer.set_instruction_cache(cache);
+        assert_eq!(computer.run().unwrap(), State::Done);
+        assert_eq!(computer.get_all_output(), vec![11, 30], "cache {}", cache);
+    }
+
+    // rewrites the target of its own jump
+    let program = [1101, 9, 0, 6, 1105, 1, 0, 99, 99, 104, 7, 99];
+    let (_, mut computer) = run(&program, &[]);
+    assert_eq!(computer.get_all_output(), vec![7]);
+}
+
+#[test]
+fn test_input_suspension() {
+    // doubles each input, for ever
+    let program = [3, 9, 1002, 9, 2, 9, 4, 9, 1105, 1, 0];
+    let mut computer = Computer::new(&program);
+    assert_eq!(computer.run().unwrap(), State::WaitingInput);
+    assert_eq!(computer.step().unwrap(), Step::WaitingInput);
+    assert_eq!(computer.run().unwrap(), State::WaitingInput);
+    assert!(computer.get_all_output().is_empty());
+
+    computer.add_input(21);
+    assert_eq!(computer.run().unwrap(), State::WaitingInput);
+    assert_eq!(computer.get_all_output(), vec![42]);
+
+    // the waiting instruction is part of a snapshot and resumes in a new computer
+    let snapshot = computer.snapshot();
+    let mut resumed = Computer::from_snapshot(snapshot);
+    resumed.add_input(5);
+    computer.add_input(5);
+    assert_eq!(resumed.run().unwrap(), State::WaitingInput);
+    assert_eq!(computer.run().unwrap(), State::WaitingInput);
+    assert_eq!(resumed.get_all_output(), vec![10]);
+    assert_eq!(computer.get_all_output(), vec![10]);
+
+    // several values queued at once are consumed one per input instruction
+    for v in 1..=3 {
+        computer.add_input(v);
+    }
+    assert_eq!(computer.run().unwrap(), State::WaitingInput);
+    assert_eq!(computer.get_all_output(), vec![2, 4, 6]);
+    assert!(!computer.has_input());
+}
+
+#[test]
+fn test_errors() {
+    let error = |program: &[i128]| Computer::new(program).run().unwrap_err();
+
+    assert!(matches!(
+        error(&[1101, 1, 1, 5, 0, 98]),
+        VmError::BadOpcode { ip: 4, opcode: 0 }
+    ));
+    assert!(matches!(
+        error(&[104, 1, -4]),
+        VmError::BadOpcode { ip: 2, opcode: -4 }
+    ));
+    assert!(matches!(
+        error(&[3001, 0, 0, 0, 99]),
+        VmError::BadMode { ip: 0, mode: 3, .. }
+    ));
+    assert!(matches!(
+        error(&[4, -1, 99]),
+        VmError::NegativeAddress {
+            ip: 0,
+            address: -1,
+            ..
+        }
+    ));
+    assert!(matches!(
+        error(&[109, -3, 21101, 1, 1, 0, 99]),
+        VmError::NegativeAddress {
+            ip: 2,
+            address: -3,
+            ..
+        }
+    ));
+
+    // an operand out of range leaves the instruction to fail again
+    let cases: [(&[i128], i128); 2] = [
+        (&[109, i128::MAX, 209, 1, 99], 209),
+        (&[109, i128::MIN, 22101, 0, -1, 0, 99], 22101),
+    ];
+    for &(program, code) in &cases {
+        let mut computer = Computer::new(program);
+        for _ in 0..2 {
+            assert!(matches!(
+                computer.run(),
+                Err(VmError::AddressOutOfRange { ip: 2, opcode }) if opcode == code
+            ));
+            assert_eq!(computer.instruction_pointer(), 2);
+        }
+    }
+
+    // a failing instruction leaves the machine as it was
+    let mut computer = Computer::new(&[1101, 1, 1, 7, 3, -1, 99, 0]);
+    computer.add_input(9);
+    assert!(computer.run().is_err());
+    assert_eq!(computer.instruction_pointer(), 4);
+    assert_eq!(computer.memread(7), 2);
+    assert!(computer.has_input());
+}
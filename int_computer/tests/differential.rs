@@ -0,0 +1,322 @@
+//! Runs random programs on `Computer` and on a deliberately simple reference interpreter and
+//! checks that both end in the same state.
+
+extern crate int_computer;
+use int_computer::computer::{Computer, RunOptions, State, VmError};
+use int_computer::memory::{FlatMemory, Memory, SparseMemory};
+use std::collections::HashMap;
+
+/// Instructions each run may execute before it is cut short.
+const BUDGET: u64 = 500;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Outcome {
+    Halted,
+    WaitingInput,
+    OutOfBudget,
+    /// The instruction at `ip` failed; which error is given by its name.
+    Error(&'static str, i128),
+}
+
+/// The Intcode machine written straight from the specification, one word at a time.
+struct Reference {
+    memory: HashMap<i128, i128>,
+    /// One past the highest address loaded or written; the program halts outside `0..end`.
+    end: i128,
+    ip: i128,
+    rb: i128,
+    input: Vec<i128>,
+    output: Vec<i128>,
+    executed: [u64; 100],
+}
+
+impl Reference {
+    fn new(program: &[i128]) -> Reference {
+        Reference {
+            memory: (0..).zip(program.iter().cloned()).collect(),
+            end: program.len() as i128,
+            ip: 0,
+            rb: 0,
+            input: Vec::new(),
+            output: Vec::new(),
+            executed: [0; 100],
+        }
+    }
+
+    fn read(&self, address: i128) -> i128 {
+        self.memory.get(&address).cloned().unwrap_or(0)
+    }
+
+    /// Address parameter `i` of the current instruction refers to.
+    fn address(&self, i: i128) -> Result<i128, &'static str> {
+        let param = self.read(self.ip + 1 + i);
+        let address = match self.read(self.ip) / 10i128.pow(i as u32 + 2) % 10 {
+            0 => param,
+            1 => self.ip + 1 + i,
+            _ => self.rb.checked_add(param).ok_or("AddressOutOfRange")?,
+        };
+        if address < 0 {
+            return Err("NegativeAddress");
+        }
+        Ok(address)
+    }
+
+    fn get(&self, i: i128) -> Result<i128, &'static str> {
+        Ok(self.read(self.address(i)?))
+    }
+
+    fn set(&mut self, i: i128, value: i128) -> Result<(), &'static str> {
+        let address = self.address(i)?;
+        self.memory.insert(address, value);
+        self.end = self.end.max(address.saturating_add(1));
+        Ok(())
+    }
+
+    fn run(&mut self, budget: u64) -> Outcome {
+        for _ in 0..budget {
+            match self.step() {
+                Ok(None) => {}
+                Ok(Some(outcome)) => return outcome,
+                Err(error) => return Outcome::Error(error, self.ip),
+            }
+        }
+        Outcome::OutOfBudget
+    }
+
+    /// Executes one instruction, or tells why the program stopped instead.
+    fn step(&mut self) -> Result<Option<Outcome>, &'static str> {
+        if self.ip < 0 || self.ip >= self.end {
+            return Ok(Some(Outcome::Halted));
+        }
+        let opcode = self.read(self.ip);
+        let arity = match opcode % 100 {
+            1 | 2 | 7 | 8 => 3,
+            5 | 6 => 2,
+            3 | 4 | 9 => 1,
+            99 => 0,
+            _ => return Err("BadOpcode"),
+        };
+        let mut modes = opcode / 100;
+        for _ in 0..arity {
+            if modes % 10 > 2 {
+                return Err("BadMode");
+            }
+            modes /= 10;
+        }
+        let mut next = self.ip + 1 + arity;
+        match opcode % 100 {
+            1 => {
+                let sum = self.get(0)?.checked_add(self.get(1)?).ok_or("Overflow")?;
+                self.set(2, sum)?;
+            }
+            2 => {
+                let product = self.get(0)?.checked_mul(self.get(1)?).ok_or("Overflow")?;
+                self.set(2, product)?;
+            }
+            3 => {
+                if self.input.is_empty() {
+                    return Ok(Some(Outcome::WaitingInput));
+                }
+                let value = self.input[0];
+                self.set(0, value)?;
+                self.input.remove(0);
+            }
+            4 => {
+                let value = self.get(0)?;
+                self.output.push(value);
+            }
+            5 => {
+                if self.get(0)? != 0 {
+                    next = self.get(1)?;
+                }
+            }
+            6 => {
+                if self.get(0)? == 0 {
+                    next = self.get(1)?;
+                }
+            }
+            7 => {
+                let less = self.get(0)? < self.get(1)?;
+                self.set(2, less as i128)?;
+            }
+            8 => {
+                let equal = self.get(0)? == self.get(1)?;
+                self.set(2, equal as i128)?;
+            }
+            9 => {
+                self.rb = self
+                    .rb
+                    .checked_add(self.get(0)?)
+                    .ok_or("AddressOutOfRange")?
+            }
+            _ => return Ok(Some(Outcome::Halted)),
+        }
+        self.executed[(opcode % 100) as usize] += 1;
+        self.ip = next;
+        Ok(None)
+    }
+}
+
+/// Seeded xorshift generator, so a failing case can be replayed from its seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn range(&mut self, low: i128, high: i128) -> i128 {
+        low + self.below((high - low) as u64) as i128
+    }
+}
+
+/// A program of mostly well-formed instructions whose operands point into the program, with
+/// jumps aimed at instruction starts, mixed with the odd bad opcode, bad mode or wild value.
+fn random_program(rng: &mut Rng) -> Vec<i128> {
+    let count = rng.range(4, 24) as usize;
+    let codes = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+    let mut instructions: Vec<(i128, Vec<i128>)> = Vec::new();
+    let mut starts = Vec::new();
+    let mut size: i128 = 0;
+    for _ in 0..count {
+        let code = codes[rng.below(codes.len() as u64) as usize];
+        let arity: u32 = match code {
+            1 | 2 | 7 | 8 => 3,
+            5 | 6 => 2,
+            3 | 4 | 9 => 1,
+            _ => 0,
+        };
+        let mut opcode = code;
+        for i in 0..arity {
+            let mode = match rng.below(20) {
+                0 => 3,
+                n => n as i128 % 3,
+            };
+            opcode += mode * 10i128.pow(i + 2);
+        }
+        let params = (0..arity).map(|_| rng.range(-2, 48)).collect();
+        starts.push(size);
+        size += 1 + arity as i128;
+        instructions.push((opcode, params));
+    }
+    let mut program = Vec::new();
+    for (opcode, mut params) in instructions {
+        if (opcode % 100 == 5 || opcode % 100 == 6) && rng.below(2) == 0 {
+            params[1] = starts[rng.below(starts.len() as u64) as usize];
+        }
+        program.push(opcode);
+        program.extend(params);
+    }
+    // data the operands can reach
+    for _ in 0..8 {
+        program.push(rng.range(-10, 10));
+    }
+    for _ in 0..rng.below(3) {
+        let at = rng.below(program.len() as u64) as usize;
+        program[at] = match rng.below(3) {
+            0 => rng.range(-100, 100),
+            1 => 1 << 100,
+            _ => rng.next() as i128,
+        };
+    }
+    program
+}
+
+fn error_name(error: &VmError) -> &'static str {
+    match error {
+        VmError::BadOpcode { .. } => "BadOpcode",
+        VmError::BadMode { .. } => "BadMode",
+        VmError::NegativeAddress { .. } => "NegativeAddress",
+        VmError::Overflow { .. } => "Overflow",
+        VmError::AddressOutOfRange { .. } => "AddressOutOfRange",
+        _ => "other",
+    }
+}
+
+fn outcome<M: Memory<Word = i128>>(computer: &mut Computer<M>) -> Outcome {
+    match computer.run_with(&RunOptions::new().budget(BUDGET)) {
+        Ok(State::Done) => Outcome::Halted,
+        Ok(State::WaitingInput) => Outcome::WaitingInput,
+        Ok(State::BudgetExhausted) => Outcome::OutOfBudget,
+        Ok(state) => panic!("unexpected state {:?}", state),
+        Err(error) => Outcome::Error(error_name(&error), error.ip().unwrap()),
+    }
+}
+
+/// Runs `program` on `computer` and on the reference, starting with `input` queued and
+/// feeding both the same values whenever they wait for more, and compares them after every
+/// run. Returns the reference for its opcode counts.
+fn compare<M: Memory<Word = i128>>(
+    seed: u64,
+    program: &[i128],
+    input: &[i128],
+    mut computer: Computer<M>,
+) -> Reference {
+    let mut reference = Reference::new(program);
+    for &value in input {
+        reference.input.push(value);
+        computer.add_input_128(value);
+    }
+    let mut rng = Rng(seed);
+    for round in 0..4 {
+        let expected = reference.run(BUDGET);
+        let actual = outcome(&mut computer);
+        let context = format!("seed {} round {} program {:?}", seed, round, program);
+        assert_eq!(actual, expected, "{}", context);
+        assert_eq!(computer.get_all_output(), reference.output, "{}", context);
+        reference.output.clear();
+        assert_eq!(computer.relative_base(), reference.rb, "{}", context);
+        // a waiting computer has already moved past the input instruction
+        if expected != Outcome::WaitingInput {
+            assert_eq!(computer.instruction_pointer(), reference.ip, "{}", context);
+        }
+        let mut words: Vec<(i128, i128)> = computer.memory().words();
+        words.retain(|w| w.1 != 0);
+        let mut expected_words: Vec<(i128, i128)> =
+            reference.memory.iter().map(|(&a, &v)| (a, v)).collect();
+        expected_words.retain(|w| w.1 != 0);
+        expected_words.sort_unstable();
+        assert_eq!(words, expected_words, "{}", context);
+        assert_eq!(computer.memory().end(), reference.end, "{}", context);
+
+        if expected != Outcome::WaitingInput {
+            break;
+        }
+        let value = rng.range(-50, 50);
+        reference.input.push(value);
+        computer.add_input_128(value);
+    }
+    reference
+}
+
+#[test]
+fn test_against_reference() {
+    let mut executed = [0; 100];
+    for seed in 1..=3000u64 {
+        let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let program = random_program(&mut rng);
+        let input: Vec<i128> = (0..rng.below(3)).map(|_| rng.range(-50, 50)).collect();
+
+        let flat = Computer::with_memory(FlatMemory::from_program(&program));
+        let reference = compare(seed, &program, &input, flat);
+        for (total, count) in executed.iter_mut().zip(reference.executed.iter()) {
+            *total += count;
+        }
+        let mut cached = Computer::with_memory(FlatMemory::from_program(&program));
+        cached.set_instruction_cache(true);
+        compare(seed, &program, &input, cached);
+        let sparse = Computer::with_memory(SparseMemory::from_program(&program));
+        compare(seed, &program, &input, sparse);
+    }
+    // make sure the programs get past their first instructions often enough to matter
+    for (code, &count) in executed.iter().enumerate().take(10).skip(1) {
+        assert!(count > 500, "opcode {} ran {} times", code, count);
+    }
+}